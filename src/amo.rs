@@ -0,0 +1,218 @@
+// Recovers at-most-one / exactly-one groups from their pairwise encoding.
+//
+// A binary clause (a \/ b) says "at most one of -a, -b". A set of literals
+// that are pairwise connected that way (a clique) is an at-most-one group, and
+// if the CNF also has the clause listing exactly the group's literals it is an
+// exactly-one group. Crossing such a group as one cardinality constraint of k
+// cubes avoids building the k(k-1)/2 binary clauses' intermediate products.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::cnf::{var, Clause, ClauseDb, Lit};
+
+// Smaller cliques are left as binary clauses, there is nothing to gain
+const MIN_GROUP: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmoGroup {
+    pub lits: Vec<Lit>,
+    // Exactly one (instead of at most one) of lits is true
+    pub exactly_one: bool,
+}
+
+// How many groups of each kind were found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AmoStats {
+    pub at_most_one: usize,
+    pub exactly_one: usize,
+}
+
+impl AmoStats {
+    pub fn add(&mut self, groups: &[AmoGroup]) {
+        let eo = groups.iter().filter(|g| g.exactly_one).count();
+        self.at_most_one += groups.len() - eo;
+        self.exactly_one += eo;
+    }
+}
+
+impl fmt::Display for AmoStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AMO: found {} at-most-one and {} exactly-one groups",
+            self.at_most_one, self.exactly_one
+        )
+    }
+}
+
+fn sorted(clause: &[Lit]) -> Clause {
+    let mut key = clause.to_vec();
+    key.sort_unstable();
+    key.dedup();
+    key
+}
+
+/**
+ * Spec: finds AMO/EO groups greedily (largest degree first) and removes the
+ * clauses they replace from db: the pairwise binary clauses and, for EO
+ * groups, the clause over all of the group's literals.
+ * The groups together with the remaining clauses are equivalent to db.
+ */
+pub fn extract_groups(db: &mut ClauseDb) -> Vec<AmoGroup> {
+    // Each edge {u, v} of the "at most one" graph maps to its binary clause
+    let mut edges: HashMap<(Lit, Lit), usize> = HashMap::new();
+    let mut adj: HashMap<Lit, Vec<Lit>> = HashMap::new();
+    for (idx, clause) in db.clauses.iter().enumerate() {
+        let key = sorted(clause);
+        if let [a, b] = key[..] {
+            if a == -b {
+                continue;
+            }
+            let (u, v) = (-a, -b);
+            let edge = (u.min(v), u.max(v));
+            // A repeated binary clause stays in db, it is redundant anyway
+            if let std::collections::hash_map::Entry::Vacant(e) = edges.entry(edge) {
+                e.insert(idx);
+                adj.entry(u).or_default().push(v);
+                adj.entry(v).or_default().push(u);
+            }
+        }
+    }
+
+    let mut nodes: Vec<Lit> = adj.keys().copied().collect();
+    nodes.sort_unstable_by_key(|&l| (std::cmp::Reverse(adj[&l].len()), var(l), l));
+
+    let mut used: HashSet<(Lit, Lit)> = HashSet::new();
+    let mut groups: Vec<Vec<Lit>> = Vec::new();
+    let free_edge = |u: Lit, v: Lit, used: &HashSet<(Lit, Lit)>| {
+        let edge = (u.min(v), u.max(v));
+        edges.contains_key(&edge) && !used.contains(&edge)
+    };
+    for &start in &nodes {
+        let mut candidates: Vec<Lit> = adj[&start]
+            .iter()
+            .copied()
+            .filter(|&v| free_edge(start, v, &used))
+            .collect();
+        if candidates.len() + 1 < MIN_GROUP {
+            continue;
+        }
+        candidates.sort_unstable_by_key(|&l| (std::cmp::Reverse(adj[&l].len()), var(l), l));
+        let mut clique = vec![start];
+        for cand in candidates {
+            if clique.iter().all(|&member| free_edge(member, cand, &used)) {
+                clique.push(cand);
+            }
+        }
+        if clique.len() < MIN_GROUP {
+            continue;
+        }
+        for (i, &u) in clique.iter().enumerate() {
+            for &v in &clique[i + 1..] {
+                used.insert((u.min(v), u.max(v)));
+            }
+        }
+        groups.push(clique);
+    }
+    if groups.is_empty() {
+        return Vec::new();
+    }
+
+    let mut removed = vec![false; db.clauses.len()];
+    for edge in &used {
+        removed[edges[edge]] = true;
+    }
+    // The at-least-one half of an exactly-one group
+    let mut by_key: HashMap<Clause, usize> = HashMap::new();
+    for (idx, clause) in db.clauses.iter().enumerate() {
+        if !removed[idx] {
+            by_key.entry(sorted(clause)).or_insert(idx);
+        }
+    }
+    let groups: Vec<AmoGroup> = groups
+        .into_iter()
+        .map(|lits| {
+            let exactly_one = match by_key.remove(&sorted(&lits)) {
+                Some(idx) => {
+                    removed[idx] = true;
+                    true
+                }
+                None => false,
+            };
+            AmoGroup { lits, exactly_one }
+        })
+        .collect();
+
+    let clauses = std::mem::take(&mut db.clauses);
+    db.clauses = clauses
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, gone)| !gone)
+        .map(|(clause, _)| clause)
+        .collect();
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::testing::{assignments, db, satisfies};
+
+    // The groups together with the remaining clauses, evaluated in a model
+    fn holds(groups: &[AmoGroup], clauses: &[Clause], model: &crate::cnf::Model) -> bool {
+        groups.iter().all(|g| {
+            let n = g
+                .lits
+                .iter()
+                .filter(|&&l| crate::cnf::lit_true(model, l))
+                .count();
+            n <= 1 && (n == 1 || !g.exactly_one)
+        }) && satisfies(model, clauses)
+    }
+
+    #[test]
+    fn recovers_an_exactly_one_group() {
+        let mut cnf = db(4, &[&[-1, -2], &[-1, -3], &[-2, -3], &[1, 2, 3], &[3, 4]]);
+        let groups = extract_groups(&mut cnf);
+        assert_eq!(groups.len(), 1);
+        assert!(groups[0].exactly_one);
+        assert_eq!(sorted(&groups[0].lits), vec![1, 2, 3]);
+        assert_eq!(cnf.clauses, vec![vec![3, 4]]);
+    }
+
+    #[test]
+    fn leaves_small_cliques_alone() {
+        let mut cnf = db(3, &[&[-1, -2], &[-2, -3]]);
+        assert!(extract_groups(&mut cnf).is_empty());
+        assert_eq!(cnf.clauses.len(), 2);
+    }
+
+    #[test]
+    fn groups_are_equivalent_to_the_clauses() {
+        // An AMO over 1..4 over negated literals, sharing an edge with 5
+        let original = db(
+            5,
+            &[
+                &[1, 2],
+                &[1, 3],
+                &[1, 4],
+                &[2, 3],
+                &[2, 4],
+                &[3, 4],
+                &[4, 5],
+                &[-5, 1],
+            ],
+        );
+        let mut reduced = original.clone();
+        let groups = extract_groups(&mut reduced);
+        let mut stats = AmoStats::default();
+        stats.add(&groups);
+        assert_eq!(stats.at_most_one, 1);
+        for model in assignments(5) {
+            assert_eq!(
+                satisfies(&model, &original.clauses),
+                holds(&groups, &reduced.clauses, &model)
+            );
+        }
+    }
+}
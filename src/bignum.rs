@@ -0,0 +1,170 @@
+use std::fmt;
+use std::ops::{Add, Mul};
+
+/**
+ * Arbitrary precision unsigned integer for model counts, which overflow any
+ * machine integer as soon as a few hundred variables are unconstrained.
+ * Little endian base 2^32 limbs, no trailing zero limbs.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> BigUint {
+        BigUint { limbs: Vec::new() }
+    }
+
+    pub fn one() -> BigUint {
+        BigUint { limbs: vec![1] }
+    }
+
+    // 2^k
+    pub fn pow2(k: usize) -> BigUint {
+        let mut limbs = vec![0; k / 32 + 1];
+        limbs[k / 32] = 1 << (k % 32);
+        BigUint { limbs }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn trim(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+    }
+
+    // self * 2^k
+    pub fn mul_pow2(&self, k: usize) -> BigUint {
+        self * &BigUint::pow2(k)
+    }
+
+    // Approximate value, inf if it does not fit an f64
+    pub fn to_f64(&self) -> f64 {
+        self.limbs
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &limb| acc * 4294967296.0 + f64::from(limb))
+    }
+
+    // Divides in place by a small divisor, returning the remainder
+    fn div_small(&mut self, divisor: u32) -> u32 {
+        let mut rem = 0u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let cur = (rem << 32) | u64::from(*limb);
+            *limb = (cur / u64::from(divisor)) as u32;
+            rem = cur % u64::from(divisor);
+        }
+        self.trim();
+        rem as u32
+    }
+}
+
+impl Add for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let len = self.limbs.len().max(other.limbs.len());
+        let mut limbs = Vec::with_capacity(len + 1);
+        let mut carry = 0u64;
+        for i in 0..len {
+            let sum = carry
+                + u64::from(*self.limbs.get(i).unwrap_or(&0))
+                + u64::from(*other.limbs.get(i).unwrap_or(&0));
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+        BigUint { limbs }
+    }
+}
+
+impl Mul for &BigUint {
+    type Output = BigUint;
+
+    fn mul(self, other: &BigUint) -> BigUint {
+        if self.is_zero() || other.is_zero() {
+            return BigUint::zero();
+        }
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let cur = u64::from(limbs[i + j]) + u64::from(a) * u64::from(b) + carry;
+                limbs[i + j] = cur as u32;
+                carry = cur >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        let mut ret = BigUint { limbs };
+        ret.trim();
+        ret
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(n: u64) -> BigUint {
+        let mut ret = BigUint {
+            limbs: vec![n as u32, (n >> 32) as u32],
+        };
+        ret.trim();
+        ret
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Peel off 9 decimal digits at a time
+        let mut n = self.clone();
+        let mut chunks = Vec::new();
+        while !n.is_zero() {
+            chunks.push(n.div_small(1_000_000_000));
+        }
+        write!(f, "{}", chunks.pop().unwrap_or(0))?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{chunk:09}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_in_decimal() {
+        assert_eq!(BigUint::zero().to_string(), "0");
+        assert_eq!(BigUint::from(1_000_000_000).to_string(), "1000000000");
+        assert_eq!(
+            BigUint::pow2(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+    }
+
+    #[test]
+    fn adds_and_multiplies_across_limbs() {
+        let max = BigUint::from(u64::MAX);
+        assert_eq!(&max + &BigUint::one(), BigUint::pow2(64));
+        assert_eq!(
+            (&max * &max).to_string(),
+            "340282366920938463426481119284349108225"
+        );
+        assert_eq!(BigUint::from(3).mul_pow2(40), BigUint::from(3 << 40));
+        assert!((&max * &BigUint::zero()).is_zero());
+    }
+
+    #[test]
+    fn approximates_as_f64() {
+        assert_eq!(BigUint::pow2(80).to_f64(), 2f64.powi(80));
+        assert_eq!(BigUint::from(12345).to_f64(), 12345.0);
+    }
+}
@@ -0,0 +1,396 @@
+use std::io::{BufRead, Write};
+
+// A DIMACS literal: v for the variable v, -v for its negation
+pub type Lit = i32;
+pub type Clause = Vec<Lit>;
+// model[v] is the value of variable v, model[0] is unused
+pub type Model = Vec<bool>;
+
+pub fn var(l: Lit) -> usize {
+    l.unsigned_abs() as usize
+}
+
+// Index of a literal into per-literal tables: 2v for v, 2v + 1 for -v
+pub fn lit_idx(l: Lit) -> usize {
+    2 * var(l) + usize::from(l < 0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardKind {
+    AtMost,
+    AtLeast,
+    Exactly,
+}
+
+// A cardinality constraint: at most / at least / exactly k of lits are true
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Card {
+    pub kind: CardKind,
+    pub k: u32,
+    pub lits: Vec<Lit>,
+}
+
+/**
+ * The clause database built from a DIMACS file: one entry per clause line,
+ * literals kept in the order they were written (without the trailing 0).
+ * Cardinality and XOR lines of the extended input syntax are kept apart.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ClauseDb {
+    pub num_vars: usize,
+    pub clauses: Vec<Clause>,
+    pub cards: Vec<Card>,
+    // The XOR of each entry's literals is true
+    pub xors: Vec<Vec<Lit>>,
+}
+
+impl ClauseDb {
+    // Only clauses, as the preprocessing passes and DIMACS tools expect
+    pub fn is_plain_cnf(&self) -> bool {
+        self.cards.is_empty() && self.xors.is_empty()
+    }
+
+    // The literals of every clause, cardinality constraint and XOR
+    pub fn constraint_lits(&self) -> impl Iterator<Item = &Vec<Lit>> {
+        let card_lits = self.cards.iter().map(|card| &card.lits);
+        self.clauses.iter().chain(card_lits).chain(&self.xors)
+    }
+}
+
+/**
+ * Spec: parses a DIMACS CNF file, one clause per line.
+ * Comment lines ("c ...") and blank lines are skipped, the "p cnf" header
+ * gives num_vars (raised if a clause mentions a larger variable).
+ * Also accepts the MiniCard style cardinality lines of "p cnf+" files:
+ *   1 -2 3 <= 2     1 -2 3 >= 2     1 -2 3 = 2
+ * and CryptoMiniSat style XOR lines ("x1 -2 3 0": 1 xor -2 xor 3 is true).
+ */
+pub fn parse_dimacs<R: BufRead>(reader: R) -> ClauseDb {
+    parse_dimacs_with_lines(reader).0
+}
+
+/**
+ * Spec: parse_dimacs, also returning the (1-based) source line number of
+ * every clause of db.clauses
+ */
+pub fn parse_dimacs_with_lines<R: BufRead>(reader: R) -> (ClauseDb, Vec<usize>) {
+    let mut db = ClauseDb::default();
+    let mut lines = Vec::new();
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line = line.expect("String needed");
+        let good_line = line.trim();
+        if good_line.is_empty() || good_line.starts_with('c') {
+            continue;
+        }
+        if good_line.starts_with('%') {
+            // SATLIB end of file marker
+            break;
+        }
+        if good_line.starts_with('p') {
+            let header: Vec<&str> = good_line.split_whitespace().collect();
+            db.num_vars = header
+                .get(2)
+                .and_then(|n| n.parse().ok())
+                .unwrap_or_else(|| panic!("CRITICAL ERROR PARSING HEADER: '{good_line}'"));
+            continue;
+        }
+        if let Some(xor_line) = good_line.strip_prefix('x') {
+            let xor = parse_clause(xor_line);
+            for &l in &xor {
+                db.num_vars = db.num_vars.max(var(l));
+            }
+            db.xors.push(xor);
+            continue;
+        }
+        if let Some(card) = parse_card(good_line) {
+            for &l in &card.lits {
+                db.num_vars = db.num_vars.max(var(l));
+            }
+            db.cards.push(card);
+            continue;
+        }
+        let clause = parse_clause(good_line);
+        for &l in &clause {
+            db.num_vars = db.num_vars.max(var(l));
+        }
+        db.clauses.push(clause);
+        lines.push(line_idx + 1);
+    }
+    (db, lines)
+}
+
+// The literals of a clause line, stopping at the terminating 0
+pub fn parse_clause(good_line: &str) -> Clause {
+    let mut clause = Vec::new();
+    for ele in good_line.split_whitespace() {
+        let val: Lit = ele
+            .parse()
+            .unwrap_or_else(|_| panic!("CRITICAL ERROR PARSING LINE: '{good_line}'"));
+        if val == 0 {
+            // 0 is the last element, so done
+            break;
+        }
+        clause.push(val);
+    }
+    clause
+}
+
+/**
+ * Spec: calls parse with the whitespace separated fields of every line that is
+ * neither blank nor a "c" comment. parse returns None for a malformed line,
+ * which panics naming the kind of line (e.g. "COST").
+ */
+pub fn parse_lines<R: BufRead>(
+    reader: R,
+    what: &str,
+    mut parse: impl FnMut(&[&str]) -> Option<()>,
+) {
+    for line in reader.lines() {
+        let line = line.expect("String needed");
+        let good_line = line.trim();
+        if good_line.is_empty() || good_line.starts_with('c') {
+            continue;
+        }
+        let fields: Vec<&str> = good_line.split_whitespace().collect();
+        if parse(&fields).is_none() {
+            panic!("CRITICAL ERROR PARSING {what} LINE: '{good_line}'");
+        }
+    }
+}
+
+// A cardinality line, None if the line has no comparison operator
+fn parse_card(good_line: &str) -> Option<Card> {
+    let tokens: Vec<&str> = good_line.split_whitespace().collect();
+    let op = tokens
+        .iter()
+        .position(|t| matches!(*t, "<=" | ">=" | "="))?;
+    let kind = match tokens[op] {
+        "<=" => CardKind::AtMost,
+        ">=" => CardKind::AtLeast,
+        _ => CardKind::Exactly,
+    };
+    let bad_line = || -> ! { panic!("CRITICAL ERROR PARSING CARDINALITY LINE: '{good_line}'") };
+    if tokens.len() != op + 2 {
+        bad_line();
+    }
+    let k: u32 = tokens[op + 1].parse().unwrap_or_else(|_| bad_line());
+    let lits = tokens[..op]
+        .iter()
+        .map(|t| match t.parse::<Lit>() {
+            Ok(l) if l != 0 => l,
+            _ => bad_line(),
+        })
+        .collect();
+    Some(Card { kind, k, lits })
+}
+
+/**
+ * Spec: writes db as DIMACS CNF with a "p cnf" header, one clause per line
+ * (a "p cnf+" header and cardinality / XOR lines if db has any)
+ */
+pub fn write_dimacs<W: Write>(db: &ClauseDb, mut w: W) -> std::io::Result<()> {
+    if db.is_plain_cnf() {
+        writeln!(w, "p cnf {} {}", db.num_vars, db.clauses.len())?;
+    } else {
+        let num = db.clauses.len() + db.cards.len() + db.xors.len();
+        writeln!(w, "p cnf+ {} {}", db.num_vars, num)?;
+    }
+    for clause in &db.clauses {
+        for l in clause {
+            write!(w, "{l} ")?;
+        }
+        writeln!(w, "0")?;
+    }
+    for card in &db.cards {
+        for l in &card.lits {
+            write!(w, "{l} ")?;
+        }
+        let op = match card.kind {
+            CardKind::AtMost => "<=",
+            CardKind::AtLeast => ">=",
+            CardKind::Exactly => "=",
+        };
+        writeln!(w, "{op} {}", card.k)?;
+    }
+    for xor in &db.xors {
+        write!(w, "x")?;
+        for l in xor {
+            write!(w, "{l} ")?;
+        }
+        writeln!(w, "0")?;
+    }
+    w.flush()
+}
+
+/**
+ * Spec: renumbers the variables occurring in db to 1..=k (keeping their
+ * order). Returns the compacted database and var_map, where var_map[new] is
+ * the original variable (var_map[0] is unused).
+ */
+pub fn compact(db: &ClauseDb) -> (ClauseDb, Vec<usize>) {
+    let mut new_var = vec![0; db.num_vars + 1];
+    for lits in db.constraint_lits() {
+        for &l in lits {
+            new_var[var(l)] = 1;
+        }
+    }
+    let mut var_map = vec![0];
+    for (v, slot) in new_var.iter_mut().enumerate().skip(1) {
+        if *slot != 0 {
+            *slot = var_map.len();
+            var_map.push(v);
+        }
+    }
+    let renumber = |lits: &[Lit]| -> Vec<Lit> {
+        lits.iter()
+            .map(|&l| {
+                let v = new_var[var(l)] as Lit;
+                if l > 0 {
+                    v
+                } else {
+                    -v
+                }
+            })
+            .collect()
+    };
+    let compacted = ClauseDb {
+        num_vars: var_map.len() - 1,
+        clauses: db.clauses.iter().map(|clause| renumber(clause)).collect(),
+        cards: db
+            .cards
+            .iter()
+            .map(|card| Card {
+                kind: card.kind,
+                k: card.k,
+                lits: renumber(&card.lits),
+            })
+            .collect(),
+        xors: db.xors.iter().map(|xor| renumber(xor)).collect(),
+    };
+    (compacted, var_map)
+}
+
+/**
+ * Spec: turns a (partial) assignment given as literals into a full model,
+ * unassigned variables default to false.
+ */
+pub fn model_from_lits(lits: &[Lit], num_vars: usize) -> Model {
+    let mut model = vec![false; num_vars + 1];
+    for &l in lits {
+        if var(l) > num_vars {
+            model.resize(var(l) + 1, false);
+        }
+        model[var(l)] = l > 0;
+    }
+    model
+}
+
+pub fn lit_true(model: &Model, l: Lit) -> bool {
+    model.get(var(l)).copied().unwrap_or(false) == (l > 0)
+}
+
+// Competition style "v 1 -2 3 0" model line
+pub fn model_line(model: &Model) -> String {
+    let mut line = String::from("v");
+    for (v, val) in model.iter().enumerate().skip(1) {
+        if *val {
+            line.push_str(&format!(" {v}"));
+        } else {
+            line.push_str(&format!(" -{v}"));
+        }
+    }
+    line.push_str(" 0");
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(db: &ClauseDb) -> String {
+        let mut out = Vec::new();
+        write_dimacs(db, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parses_clauses_with_their_lines() {
+        let (db, lines) =
+            parse_dimacs_with_lines("c comment\np cnf 3 2\n1 -2 0\n\n3 0\n%\n9 0\n".as_bytes());
+        assert_eq!(db.num_vars, 3);
+        assert_eq!(db.clauses, vec![vec![1, -2], vec![3]]);
+        assert_eq!(lines, vec![3, 5]);
+        // A clause may mention variables past the header's count
+        assert_eq!(parse_dimacs("p cnf 1 1\n1 -4 0\n".as_bytes()).num_vars, 4);
+    }
+
+    #[test]
+    fn writes_what_it_parses() {
+        let text = "p cnf 3 2\n1 -2 0\n3 0\n";
+        let db = parse_dimacs(text.as_bytes());
+        assert_eq!(written(&db), text);
+    }
+
+    #[test]
+    fn compacts_the_used_variables() {
+        let db = parse_dimacs("p cnf 9 2\n4 -9 0\n-2 4 0\n".as_bytes());
+        let (compacted, var_map) = compact(&db);
+        assert_eq!(compacted.num_vars, 3);
+        assert_eq!(compacted.clauses, vec![vec![2, -3], vec![-1, 2]]);
+        assert_eq!(var_map, vec![0, 2, 4, 9]);
+    }
+
+    #[test]
+    fn builds_models_from_literals() {
+        let model = model_from_lits(&[2, -1, 4], 3);
+        assert_eq!(model, vec![false, false, true, false, true]);
+        assert!(lit_true(&model, -3) && !lit_true(&model, 7));
+        assert_eq!(model_line(&model), "v -1 2 -3 4 0");
+    }
+
+    #[test]
+    fn parses_cardinality_lines() {
+        let db = parse_dimacs("p cnf 2 3\n1 -2 <= 1\n2 5 >= 2\n-1 2 3 = 0\n1 0\n".as_bytes());
+        let card = |kind, k, lits: &[Lit]| Card {
+            kind,
+            k,
+            lits: lits.to_vec(),
+        };
+        assert_eq!(
+            db.cards,
+            vec![
+                card(CardKind::AtMost, 1, &[1, -2]),
+                card(CardKind::AtLeast, 2, &[2, 5]),
+                card(CardKind::Exactly, 0, &[-1, 2, 3]),
+            ]
+        );
+        assert_eq!(db.clauses, vec![vec![1]]);
+        assert_eq!(db.num_vars, 5);
+        assert!(!db.is_plain_cnf());
+        assert_eq!(parse_dimacs(written(&db).as_bytes()).cards, db.cards);
+    }
+
+    #[test]
+    #[should_panic(expected = "CARDINALITY LINE")]
+    fn cardinality_lines_need_a_bound() {
+        parse_dimacs("1 2 <=\n".as_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "CARDINALITY LINE")]
+    fn cardinality_lines_have_no_terminating_zero() {
+        parse_dimacs("1 2 0 >= 1\n".as_bytes());
+    }
+
+    #[test]
+    fn parses_xor_lines() {
+        let db = parse_dimacs("p cnf 3 3\nx1 -2 0\nx 3 7 0\n-1 0\n".as_bytes());
+        assert_eq!(db.xors, vec![vec![1, -2], vec![3, 7]]);
+        assert_eq!(db.clauses, vec![vec![-1]]);
+        assert_eq!(db.num_vars, 7);
+        assert!(!db.is_plain_cnf());
+        let text = written(&db);
+        assert!(text.starts_with("p cnf+ 7 3\n"));
+        assert_eq!(parse_dimacs(text.as_bytes()).xors, db.xors);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::cnf::{var, ClauseDb, Lit};
+
+// Union-find root of v, with path halving
+fn find(parent: &mut [usize], mut v: usize) -> usize {
+    while parent[v] != v {
+        parent[v] = parent[parent[v]];
+        v = parent[v];
+    }
+    v
+}
+
+/**
+ * Spec: splits db into the connected components of its variable-clause
+ * graph: no two components share a variable, so the CNF is satisfiable iff
+ * every component is, and its models are the products of theirs.
+ * Cardinality constraints and XORs join their variables like clauses do.
+ * Components keep the original variable numbering (and num_vars).
+ * Empty clauses end up in a component of their own.
+ */
+pub fn split(db: &ClauseDb) -> Vec<ClauseDb> {
+    let mut parent: Vec<usize> = (0..=db.num_vars).collect();
+    for lits in db.constraint_lits() {
+        if let Some(&first) = lits.first() {
+            let root = find(&mut parent, var(first));
+            for &l in &lits[1..] {
+                let other = find(&mut parent, var(l));
+                parent[other] = root;
+            }
+        }
+    }
+
+    // comp_of[root] = index of that root's component in the result
+    let mut comp_of = vec![usize::MAX; db.num_vars + 1];
+    let mut comps: Vec<ClauseDb> = Vec::new();
+    let mut comp_for = |lits: &[Lit], comps: &mut Vec<ClauseDb>| -> usize {
+        let root = match lits.first() {
+            Some(&first) => find(&mut parent, var(first)),
+            None => 0,
+        };
+        if comp_of[root] == usize::MAX {
+            comp_of[root] = comps.len();
+            comps.push(ClauseDb {
+                num_vars: db.num_vars,
+                ..ClauseDb::default()
+            });
+        }
+        comp_of[root]
+    };
+    for clause in &db.clauses {
+        let idx = comp_for(clause, &mut comps);
+        comps[idx].clauses.push(clause.clone());
+    }
+    for card in &db.cards {
+        let idx = comp_for(&card.lits, &mut comps);
+        comps[idx].cards.push(card.clone());
+    }
+    for xor in &db.xors {
+        let idx = comp_for(xor, &mut comps);
+        comps[idx].xors.push(xor.clone());
+    }
+    comps
+}
+
+// The distinct variables mentioned by db's constraints
+pub fn num_vars_used(db: &ClauseDb) -> usize {
+    let mut seen = vec![false; db.num_vars + 1];
+    for lits in db.constraint_lits() {
+        for &l in lits {
+            seen[var(l)] = true;
+        }
+    }
+    seen.iter().filter(|s| **s).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cnf::{parse_dimacs, Card, CardKind};
+    use crate::preprocess::testing::{db, is_sat, random_cnfs};
+
+    #[test]
+    fn splits_on_shared_variables() {
+        let mut input = db(6, &[&[1, -2], &[3], &[2, 4], &[]]);
+        input.cards.push(Card {
+            kind: CardKind::AtMost,
+            k: 1,
+            lits: vec![3, 5],
+        });
+        input.xors.push(vec![6, 1]);
+        let comps = split(&input);
+        assert_eq!(comps.len(), 3);
+        assert_eq!(comps[0].clauses, vec![vec![1, -2], vec![2, 4]]);
+        assert_eq!(comps[0].xors, vec![vec![6, 1]]);
+        assert_eq!(comps[1].clauses, vec![vec![3]]);
+        assert_eq!(comps[1].cards, input.cards);
+        assert_eq!(comps[2].clauses, vec![vec![]]);
+        assert!(comps.iter().all(|c| c.num_vars == 6));
+    }
+
+    #[test]
+    fn satisfiable_iff_every_component_is() {
+        for cnf in random_cnfs(33, 200) {
+            let comps = split(&cnf);
+            let total: usize = comps.iter().map(|c| c.clauses.len()).sum();
+            assert_eq!(total, cnf.clauses.len());
+            let used: usize = comps.iter().map(num_vars_used).sum();
+            assert_eq!(used, num_vars_used(&cnf));
+            assert_eq!(comps.iter().all(is_sat), is_sat(&cnf));
+        }
+    }
+
+    #[test]
+    fn counts_the_mentioned_variables() {
+        let cnf = parse_dimacs("p cnf 9 2\n1 -4 0\n4 7 <= 1\n".as_bytes());
+        assert_eq!(num_vars_used(&cnf), 3);
+    }
+}
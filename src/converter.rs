@@ -1,4 +1,5 @@
 pub mod formula;
+pub mod partition;
 use core::fmt;
 use std::{
     env,
@@ -196,14 +197,52 @@ fn line_vec_to_formula(v: &[Result<String, std::io::Error>]) -> Box<Formula> {
     Box::new(FConj(v1_r, v2_r))
 }
 
+// The variables mentioned by a clause line, used to cluster the clauses
+fn line_vars(line: &str) -> Vec<u32> {
+    line.split_whitespace()
+        .map(|ele| {
+            let val: i32 = ele
+                .parse()
+                .unwrap_or_else(|_| panic!("CRITICAL ERROR PARSING LINE: '{line}'"));
+            val.unsigned_abs()
+        })
+        .filter(|v| *v != 0)
+        .collect()
+}
+
+/**
+ * Same as line_vec_to_formula, but instead of splitting the lines by position
+ * each FConj splits them along a min-cut bisection of the variable sharing
+ * hypergraph (see partition.rs). Clauses over the same variables end up in the
+ * same subtree, so each rayon::join branch produces a smaller partial DNF.
+ * Invariant: lines and vars are parallel and non-empty
+ */
+fn line_vec_to_formula_clustered(lines: &[&String], vars: &[Vec<u32>]) -> Box<Formula> {
+    let v_len = lines.len();
+    if v_len == 1 {
+        return proc_line(lines[0]);
+    }
+    let (left, right) = partition::bisect(vars);
+    let (l_lines, l_vars): (Vec<&String>, Vec<Vec<u32>>) =
+        left.iter().map(|&i| (lines[i], vars[i].clone())).unzip();
+    let (r_lines, r_vars): (Vec<&String>, Vec<Vec<u32>>) =
+        right.iter().map(|&i| (lines[i], vars[i].clone())).unzip();
+    let (v1_r, v2_r) = rayon::join(
+        || line_vec_to_formula_clustered(&l_lines, &l_vars),
+        || line_vec_to_formula_clustered(&r_lines, &r_vars),
+    );
+    Box::new(FConj(v1_r, v2_r))
+}
+
 // (1 \/ 2) /\ (-1 \/ 2)
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     dbg!(&args);
 
-    if args.len() != 2 {
-        eprintln!("Usage: {} <input_file>", args[0]);
+    let cluster = args.len() == 3 && args[2] == "--cluster";
+    if args.len() != 2 && !cluster {
+        eprintln!("Usage: {} <input_file> [--cluster]", args[0]);
         std::process::exit(-1);
     }
 
@@ -224,7 +263,16 @@ fn main() {
 
     // NOTE: There is some nuance to this, would we rather have extra threads or exactly as many threads as cores?
     let vec_lines: Vec<Result<String, std::io::Error>> = lines.collect();
-    let formula = line_vec_to_formula(&vec_lines);
+    let formula = if cluster {
+        let good_lines: Vec<&String> = vec_lines
+            .iter()
+            .map(|line| line.as_ref().expect("Needed a string value"))
+            .collect();
+        let vars: Vec<Vec<u32>> = good_lines.iter().map(|line| line_vars(line)).collect();
+        line_vec_to_formula_clustered(&good_lines, &vars)
+    } else {
+        line_vec_to_formula(&vec_lines)
+    };
     // lines.take(num_lines.try_into().unwrap()).collect();
     println!("Welcome to Converter");
     println!(
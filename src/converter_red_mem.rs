@@ -1,10 +1,29 @@
+pub mod amo;
+pub mod bignum;
+pub mod cnf;
+pub mod components;
+pub mod dnf;
+pub mod lex;
+pub mod marginals;
+pub mod mus;
+pub mod opb;
+pub mod preprocess;
+pub mod proof;
+pub mod provenance;
+pub mod wcnf;
+pub mod xor;
+
+use bignum::BigUint;
+use cnf::{Card, CardKind, Clause, ClauseDb, Lit};
 use core::panic;
+use dnf::Cube;
 use std::{
     borrow::BorrowMut,
+    collections::HashMap,
     env,
     fs::File,
-    io::{BufRead, BufReader},
-    sync::{atomic::AtomicBool, Arc, Mutex},
+    io::{BufReader, BufWriter},
+    sync::{Arc, Mutex},
 };
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone)]
@@ -13,16 +32,22 @@ enum Formula {
     FNeg(Box<Formula>),
     FDisj(Vec<Formula>),
     FConj(Vec<Formula>),
+    // Cardinality constraints: at most / at least / exactly k of the formulas hold
+    FAtMost(u32, Vec<Formula>),
+    FAtLeast(u32, Vec<Formula>),
+    FExactly(u32, Vec<Formula>),
+    // Parity: an odd number of the formulas hold
+    FXor(Vec<Formula>),
 }
 use crossbeam::atomic::AtomicCell;
 use rayon::{
     current_thread_index,
     iter::{
-        IntoParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelBridge,
-        ParallelIterator,
+        IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+        IntoParallelRefMutIterator, ParallelBridge, ParallelIterator,
     },
 };
-use Formula::{FConj, FDisj, FNeg, FVar};
+use Formula::{FAtLeast, FAtMost, FConj, FDisj, FExactly, FNeg, FVar, FXor};
 
 // Invariant: Every sub-formula is already flat and DNF
 fn flatten(f: Formula) -> Formula {
@@ -33,6 +58,8 @@ fn flatten(f: Formula) -> Formula {
             FNeg(f_bot) => *f_bot,
             FConj(_) => panic!("Cannot have Conj below Disj"),
             FDisj(_) => panic!("Cannot have Disj below Conj"),
+            FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Cannot have Card below Neg"),
+            FXor(_) => panic!("Cannot have Xor below Neg"),
         },
         FDisj(fvec) => {
             let mut ret_vec = Vec::new();
@@ -41,7 +68,13 @@ fn flatten(f: Formula) -> Formula {
                     FVar(_) => ret_vec.push(fv),
                     FNeg(_) => ret_vec.push(fv),
                     FDisj(mut fvec_nested) => ret_vec.append(&mut fvec_nested),
-                    FConj(mut fvec_nested) => ret_vec.append(&mut fvec_nested),
+                    // A conj below a disj is one of its cubes and stays whole:
+                    // splicing its literals into the disj would turn x /\ y
+                    // into x \/ y (to_dnf only used to produce single-literal
+                    // disjuncts, cardinality and XOR DNFs have wider cubes)
+                    FConj(_) => ret_vec.push(fv),
+                    FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card is not DNF"),
+                    FXor(_) => panic!("Xor is not DNF"),
                 }
             }
             FDisj(ret_vec)
@@ -54,10 +87,14 @@ fn flatten(f: Formula) -> Formula {
                     FNeg(_) => ret_vec.push(fv),
                     FDisj(_) => panic!("Should not have Disj below Conj"),
                     FConj(mut fvec_nested) => ret_vec.append(&mut fvec_nested),
+                    FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card is not DNF"),
+                    FXor(_) => panic!("Xor is not DNF"),
                 }
             }
             FConj(ret_vec)
         }
+        FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card is not DNF"),
+        FXor(_) => panic!("Xor is not DNF"),
     }
 }
 
@@ -89,25 +126,54 @@ fn flatten(f: Formula) -> Formula {
 
 // Invariant: All formulas in fvec vector are in DNF form
 fn formula_cross(fvec: Vec<Formula>) -> Formula {
-    let mut ret_vec: Vec<Vec<Formula>> = vec![vec![]];
+    let final_vec: Vec<Formula> = cross_rows(fvec, false)
+        .into_iter()
+        .map(|(vec, _)| FConj(vec))
+        .collect();
+    FDisj(final_vec)
+}
+
+// A conj of a cross product and the disjuncts it took (see cross_rows)
+type CrossRow = (Vec<Formula>, Vec<usize>);
+
+/**
+ * The conjs of the cross product of fvec, as literal lists. With track set,
+ * each one also records the index of the disjunct it took from every
+ * formula of fvec (0 for a formula that is not a disj), for --explain.
+ * Invariant: All formulas in fvec vector are in DNF form
+ */
+fn cross_rows(fvec: Vec<Formula>, track: bool) -> Vec<CrossRow> {
+    let mut ret_vec: Vec<CrossRow> = vec![(vec![], vec![])];
     for f in fvec {
         match f {
             FVar(_) | FNeg(_) => {
-                ret_vec.par_iter_mut().for_each(|vec| {
+                ret_vec.par_iter_mut().for_each(|(vec, chosen)| {
                     vec.push(f.clone());
+                    if track {
+                        chosen.push(0);
+                    }
                 });
                 // for vec in &mut ret_vec {
                 //     vec.push(f.clone())
                 // }
             }
             FDisj(fvec_rec) => {
-                let new_ret_vec: Arc<Mutex<Vec<Vec<Formula>>>> = Arc::new(Mutex::new(Vec::new()));
-                ret_vec.par_iter().for_each(|vec| {
+                let new_ret_vec: Arc<Mutex<Vec<CrossRow>>> = Arc::new(Mutex::new(Vec::new()));
+                ret_vec.par_iter().for_each(|(vec, chosen)| {
                     let mut cur_ret_vec = vec![];
-                    for form in &fvec_rec {
+                    for (j, form) in fvec_rec.iter().enumerate() {
                         let mut cur_vec = vec.clone();
-                        cur_vec.push(form.clone());
-                        cur_ret_vec.push(cur_vec);
+                        match form {
+                            // A disjunct that is a cube adds all its literals
+                            // to the product, which stays a flat conj
+                            FConj(lits) => cur_vec.extend(lits.iter().cloned()),
+                            _ => cur_vec.push(form.clone()),
+                        }
+                        let mut cur_chosen = chosen.clone();
+                        if track {
+                            cur_chosen.push(j);
+                        }
+                        cur_ret_vec.push((cur_vec, cur_chosen));
                     }
                     new_ret_vec.lock().unwrap().extend(cur_ret_vec);
                 });
@@ -122,17 +188,35 @@ fn formula_cross(fvec: Vec<Formula>) -> Formula {
                 ret_vec = new_ret_vec.lock().unwrap().clone();
             }
             FConj(fvec_rec) => {
-                ret_vec
-                    .par_iter_mut()
-                    .for_each(|vec| vec.append(&mut fvec_rec.clone()));
+                ret_vec.par_iter_mut().for_each(|(vec, chosen)| {
+                    vec.append(&mut fvec_rec.clone());
+                    if track {
+                        chosen.push(0);
+                    }
+                });
                 // for vec in &mut ret_vec {
                 //     vec.append(&mut fvec_rec);
                 // }
             }
+            FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card is not DNF"),
+            FXor(_) => panic!("Xor is not DNF"),
         }
     }
-    let final_vec: Vec<Formula> = ret_vec.into_iter().map(|vec| FConj(vec)).collect();
-    FDisj(final_vec)
+    ret_vec
+}
+
+/**
+ * The consistent cubes of the conjunction of the DNFs, as to_dnf crosses
+ * them, each with the disjunct it took from every DNF
+ */
+fn traced_cubes(dnfs: Vec<Formula>) -> Vec<provenance::TracedCube> {
+    cross_rows(dnfs, true)
+        .into_par_iter()
+        .filter_map(|(vec, chosen)| {
+            let lits = sat_conj(FConj(vec))?;
+            Some(provenance::TracedCube { lits, chosen })
+        })
+        .collect()
 }
 
 fn to_dnf(f: Formula) -> Formula {
@@ -172,8 +256,31 @@ fn to_dnf(f: Formula) -> Formula {
                     .into_par_iter()
                     .map(|ele| to_dnf(FNeg(Box::new(ele))))
                     .collect();
-                to_dnf(flatten(FConj(ret_vec)))
+                // -(A \/ B) = -A /\ -B, and -A, -B are DNFs themselves, so
+                // they are crossed like the conjuncts of an FConj. Flattening
+                // them into one FConj only worked when each was a literal:
+                // -(x \/ (y /\ z)) has the disj -y \/ -z below the conj.
+                formula_cross(ret_vec)
+            }
+            // Not at most k = at least k + 1, and so on
+            FAtMost(k, fvec) => to_dnf(FAtLeast(k + 1, fvec)),
+            FAtLeast(0, _) => FDisj(vec![]),
+            FAtLeast(k, fvec) => to_dnf(FAtMost(k - 1, fvec)),
+            FExactly(k, fvec) => {
+                let mut ret_vec = vec![FAtLeast(k + 1, fvec.clone())];
+                if k > 0 {
+                    ret_vec.push(FAtMost(k - 1, fvec));
+                }
+                to_dnf(FDisj(ret_vec))
             }
+            // Negating one of the formulas flips the parity
+            FXor(mut fvec) => match fvec.pop() {
+                None => FDisj(vec![FConj(vec![])]),
+                Some(last) => {
+                    fvec.push(FNeg(Box::new(last)));
+                    to_dnf(FXor(fvec))
+                }
+            },
         },
         FConj(fvec) => {
             // let mut ret_vec = Vec::new();
@@ -184,31 +291,209 @@ fn to_dnf(f: Formula) -> Formula {
             let ret_vec: Vec<Formula> = fvec.into_par_iter().map(|ele| to_dnf(ele)).collect();
             formula_cross(ret_vec)
         }
+        // One conj per k-subset of fvec, each making its subset true
+        FAtLeast(k, fvec) => to_dnf(FDisj(
+            choose(fvec.len(), k as usize)
+                .into_iter()
+                .map(|subset| FConj(subset.into_iter().map(|i| fvec[i].clone()).collect()))
+                .collect(),
+        )),
+        // At most k hold iff at least n - k fail
+        FAtMost(k, fvec) => {
+            let n = fvec.len() as u32;
+            if k >= n {
+                return FDisj(vec![FConj(vec![])]);
+            }
+            let negs = fvec.into_iter().map(|f| FNeg(Box::new(f))).collect();
+            to_dnf(FAtLeast(n - k, negs))
+        }
+        // One conj per k-subset of fvec, making exactly that subset true
+        FExactly(k, fvec) => to_dnf(FDisj(
+            choose(fvec.len(), k as usize)
+                .into_iter()
+                .map(|subset| exactly_subset(&fvec, subset))
+                .collect(),
+        )),
+        // One conj per odd-sized subset of fvec, making exactly that subset true
+        FXor(fvec) => {
+            let n = fvec.len();
+            to_dnf(FDisj(
+                (1..=n)
+                    .step_by(2)
+                    .flat_map(|k| choose(n, k))
+                    .map(|subset| exactly_subset(&fvec, subset))
+                    .collect(),
+            ))
+        }
+    }
+}
+
+// The conj making the formulas at the subset's indices true and the rest false
+fn exactly_subset(fvec: &[Formula], subset: Vec<usize>) -> Formula {
+    let mut in_subset = vec![false; fvec.len()];
+    for i in subset {
+        in_subset[i] = true;
     }
+    FConj(
+        fvec.iter()
+            .zip(in_subset)
+            .map(|(f, pick)| {
+                if pick {
+                    f.clone()
+                } else {
+                    FNeg(Box::new(f.clone()))
+                }
+            })
+            .collect(),
+    )
 }
 
-fn proc_line(line: Result<String, std::io::Error>) -> Formula {
-    // println!("{}", line);
-    let good_line = line.expect("String needed").trim().replace("  ", " ");
-    let vars = good_line.split(" ");
-    let mut ret_val: Vec<Formula> = Vec::new();
-    for ele in vars {
-        let val: i32 = ele
-            .parse()
-            .expect(&format!("CRITICAL ERROR PARSING LINE: '{good_line}'"));
-        if val == 0 {
-            // 0 is the last element, so done
-            break;
+/**
+ * Spec: every k element subset of 0..n, as sorted index vectors
+ */
+fn choose(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k > n {
+        return Vec::new();
+    }
+    let mut ret_vec = Vec::new();
+    let mut subset: Vec<usize> = (0..k).collect();
+    loop {
+        ret_vec.push(subset.clone());
+        // Advance the rightmost index that still has room
+        let mut i = k;
+        while i > 0 && subset[i - 1] == n - k + i - 1 {
+            i -= 1;
         }
-        if val > 0 {
-            let val_good = val as u32;
-            ret_val.push(FVar(val_good));
-        } else {
-            let val_good = -val as u32;
-            ret_val.push(FNeg(Box::new(FVar(val_good))));
+        if i == 0 {
+            return ret_vec;
+        }
+        subset[i - 1] += 1;
+        for j in i..k {
+            subset[j] = subset[j - 1] + 1;
         }
     }
-    FDisj(ret_val)
+}
+
+fn proc_lit(val: Lit) -> Formula {
+    if val > 0 {
+        let val_good = val as u32;
+        FVar(val_good)
+    } else {
+        let val_good = -val as u32;
+        FNeg(Box::new(FVar(val_good)))
+    }
+}
+
+/**
+ * Each clause is X1 \/ ... \/ XN (with no conjs), so it is one flat FDisj
+ */
+fn proc_clause(clause: &Clause) -> Formula {
+    FDisj(clause.iter().map(|&val| proc_lit(val)).collect())
+}
+
+/**
+ * An AMO/EO group as one cardinality constraint, which to_dnf expands into
+ * one cube per literal: for AMO the others are false and it is left free, for
+ * EO it is true as well
+ */
+fn proc_group(group: &amo::AmoGroup) -> Formula {
+    let fvec = group.lits.iter().map(|&l| proc_lit(l)).collect();
+    if group.exactly_one {
+        FExactly(1, fvec)
+    } else {
+        FAtMost(1, fvec)
+    }
+}
+
+fn proc_card(card: &Card) -> Formula {
+    let fvec = card.lits.iter().map(|&l| proc_lit(l)).collect();
+    match card.kind {
+        CardKind::AtMost => FAtMost(card.k, fvec),
+        CardKind::AtLeast => FAtLeast(card.k, fvec),
+        CardKind::Exactly => FExactly(card.k, fvec),
+    }
+}
+
+fn proc_xor(xor: &[Lit]) -> Formula {
+    FXor(xor.iter().map(|&l| proc_lit(l)).collect())
+}
+
+// Cubes already are a DNF
+fn proc_cubes(cubes: Vec<Cube>) -> Formula {
+    FDisj(
+        cubes
+            .into_iter()
+            .map(|cube| FConj(cube.into_iter().map(proc_lit).collect()))
+            .collect(),
+    )
+}
+
+/**
+ * The XOR system after Gaussian elimination, one conj per free assignment.
+ * With too many free variables to enumerate, each XOR is expanded on its own.
+ */
+fn proc_xor_system(xors: &[Vec<Lit>], num_vars: usize, stats: &mut PartStats) -> Formula {
+    match xor::eliminate(xors, num_vars) {
+        Some(system) => {
+            stats.xor.add(&system);
+            match system.cubes() {
+                Some(cubes) => proc_cubes(cubes),
+                None => FConj(xors.iter().map(|xor| proc_xor(xor)).collect()),
+            }
+        }
+        None => FDisj(vec![]),
+    }
+}
+
+// What parts_of recognized, summed over the databases it was given
+#[derive(Debug, Clone, Default)]
+struct PartStats {
+    amo: amo::AmoStats,
+    xor: xor::XorStats,
+}
+
+impl std::fmt::Display for PartStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.amo != amo::AmoStats::default() {
+            writeln!(f, "{}", self.amo)?;
+        }
+        if self.xor != xor::XorStats::default() {
+            writeln!(f, "{}", self.xor)?;
+        }
+        Ok(())
+    }
+}
+
+// The conjuncts of a clause database: its clauses, cardinality constraints,
+// XORs and recognized AMO/EO groups
+fn parts_of(db: &ClauseDb, detect_amo: bool, gauss: bool, stats: &mut PartStats) -> Vec<Formula> {
+    let mut db = db.clone();
+    let groups = if detect_amo {
+        amo::extract_groups(&mut db)
+    } else {
+        Vec::new()
+    };
+    stats.amo.add(&groups);
+    let mut ret_form_vec: Vec<Formula> = groups.iter().map(proc_group).collect();
+    ret_form_vec.extend(db.cards.iter().map(proc_card));
+    if gauss && !db.xors.is_empty() {
+        ret_form_vec.push(proc_xor_system(&db.xors, db.num_vars, stats));
+    } else {
+        ret_form_vec.extend(db.xors.iter().map(|xor| proc_xor(xor)));
+    }
+    ret_form_vec.extend(db.clauses.iter().map(proc_clause));
+    ret_form_vec
+}
+
+// The DNF of a clause database, crossing each of its parts whole; for callers
+// compiling many databases, which do not report what was recognized
+fn dnf_of(db: &ClauseDb, detect_amo: bool, gauss: bool) -> Formula {
+    to_dnf(FConj(parts_of(
+        db,
+        detect_amo,
+        gauss,
+        &mut PartStats::default(),
+    )))
 }
 
 fn disj_below_neg(f: Formula, in_neg: bool) -> bool {
@@ -234,6 +519,8 @@ fn disj_below_neg(f: Formula, in_neg: bool) -> bool {
             }
             false
         }
+        // A cardinality constraint is a disjunction in disguise
+        FAtMost(..) | FAtLeast(..) | FExactly(..) | FXor(_) => true,
     }
 }
 
@@ -260,6 +547,7 @@ fn disj_below_conj(f: Formula, in_conj: bool) -> bool {
             }
             false
         }
+        FAtMost(..) | FAtLeast(..) | FExactly(..) | FXor(_) => true,
     }
 }
 
@@ -289,6 +577,7 @@ fn dnf_spec(f: Formula, in_neg: bool, in_conj: bool, in_disj: bool) -> bool {
             }
             true
         }
+        FAtMost(..) | FAtLeast(..) | FExactly(..) | FXor(_) => false,
     }
 }
 
@@ -299,7 +588,7 @@ fn dnf_spec(f: Formula, in_neg: bool, in_conj: bool, in_disj: bool) -> bool {
  * If safe_insert_vec returns FALSE => UNSAT
  * If safe_insert_vec returns TRUE => SAT and val \in v now
  */
-fn safe_insert_vec(v: &mut Vec<i64>, val: i64) -> bool {
+fn safe_insert_vec(v: &mut Vec<Lit>, val: Lit) -> bool {
     if v.contains(&-val) {
         return false;
     }
@@ -307,7 +596,10 @@ fn safe_insert_vec(v: &mut Vec<i64>, val: i64) -> bool {
     true
 }
 
-fn sat_conj(f: Formula) -> bool {
+/**
+ * Returns the literals of the conj if they are consistent, None => UNSAT
+ */
+fn sat_conj(f: Formula) -> Option<Vec<Lit>> {
     let mut count_vec = Vec::new();
     match f {
         FVar(_) => panic!("Var inside conj"),
@@ -317,84 +609,1608 @@ fn sat_conj(f: Formula) -> bool {
             for v in vec {
                 match v {
                     FVar(x) => {
-                        let xi: i64 = x.into();
+                        let xi = x as Lit;
                         if !safe_insert_vec(&mut count_vec, xi) {
-                            return false;
+                            return None;
                         }
                     }
                     FNeg(frr) => match *frr {
                         FVar(x) => {
-                            let xi: i64 = (x).into();
+                            let xi = x as Lit;
                             let x_neg = -xi;
                             if !safe_insert_vec(&mut count_vec, x_neg) {
-                                return false;
+                                return None;
                             }
                         }
                         FNeg(_) => panic!("Neg in Neg in Conj"),
                         FDisj(_) => panic!("Disj in Neg in Conj"),
                         FConj(_) => panic!("Conj in Neg in Conj"),
+                        FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card in Neg in Conj"),
+                        FXor(_) => panic!("Xor in Neg in Conj"),
                     },
                     FDisj(_) => panic!("Disj inside conj"),
                     FConj(_) => panic!("Non flat conj"),
+                    FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card inside conj"),
+                    FXor(_) => panic!("Xor inside conj"),
                 }
             }
-            true
+            Some(count_vec)
         }
+        FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card is not DNF"),
+        FXor(_) => panic!("Xor is not DNF"),
     }
 }
 
-fn sat(f: Formula) -> bool {
-    let solution_found = AtomicBool::new(false);
+/**
+ * Returns the literals of some consistent conj of the DNF (the witness), or
+ * None if every conj is inconsistent (UNSAT)
+ */
+fn sat(f: Formula) -> Option<Vec<Lit>> {
+    match f {
+        FVar(_) => panic!("Top level var"),
+        FNeg(_) => panic!("Top level neg"),
+        FDisj(ret_vec) => ret_vec.into_par_iter().find_map_any(sat_conj),
+        FConj(_) => panic!("Why top level conj!"),
+        FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card is not DNF"),
+        FXor(_) => panic!("Xor is not DNF"),
+    }
+}
 
+/**
+ * The consistent conjs of a DNF as cubes (the inconsistent ones have no models)
+ */
+fn cubes(f: Formula) -> Vec<Cube> {
     match f {
         FVar(_) => panic!("Top level var"),
         FNeg(_) => panic!("Top level neg"),
-        FDisj(ret_vec) => ret_vec.into_par_iter().any(|vec| {
-            if solution_found.load(std::sync::atomic::Ordering::Relaxed) {
-                return true;
-            } else {
-                let res = sat_conj(vec);
-                if res {
-                    solution_found.store(true, std::sync::atomic::Ordering::Relaxed);
+        FDisj(ret_vec) => ret_vec.into_par_iter().filter_map(sat_conj).collect(),
+        FConj(_) => panic!("Why top level conj!"),
+        FAtMost(..) | FAtLeast(..) | FExactly(..) => panic!("Card is not DNF"),
+        FXor(_) => panic!("Xor is not DNF"),
+    }
+}
+
+/**
+ * The CNF of f as an FConj of flat FDisjs, the dual of to_dnf: the cubes of
+ * to_dnf(-f), each negated into a clause (a subsumed cube gives a subsumed
+ * clause, so those are dropped)
+ */
+fn to_cnf(f: Formula) -> Formula {
+    // Wrapped in a conj, so a literal's DNF is a disj of cubes as well
+    let cubes = dnf::remove_subsumed(cubes(to_dnf(FConj(vec![FNeg(Box::new(f))]))));
+    FConj(
+        cubes
+            .into_par_iter()
+            .map(|cube| FDisj(cube.into_iter().map(|l| proc_lit(-l)).collect()))
+            .collect(),
+    )
+}
+
+// The literal of an FVar or a negated FVar
+fn lit_of(f: &Formula) -> Lit {
+    match f {
+        FVar(x) => *x as Lit,
+        FNeg(frr) => match **frr {
+            FVar(x) => -(x as Lit),
+            _ => panic!("Not a literal"),
+        },
+        _ => panic!("Not a literal"),
+    }
+}
+
+/**
+ * The clauses of a CNF produced by to_cnf
+ */
+fn clauses(f: Formula) -> Vec<Clause> {
+    match f {
+        FConj(fvec) => fvec
+            .into_par_iter()
+            .map(|clause| match clause {
+                FDisj(lits) => lits.iter().map(lit_of).collect(),
+                _ => panic!("Non-flat CONJ"),
+            })
+            .collect(),
+        _ => panic!("Top level is not a conj"),
+    }
+}
+
+/**
+ * Tseitin encoding: every compound sub-formula gets a fresh variable defined
+ * by clauses, equal sub-formulas share theirs. With polarity (Plaisted-
+ * Greenbaum) a definition is only emitted in the directions the occurrences
+ * need: x -> g where g occurs positively, g -> x where it occurs negatively.
+ * Cardinality nodes become sequential counters and XORs chains of binary
+ * XORs, both built from gates with their own variables, so their clauses grow
+ * linearly (with n * k for a counter) instead of with the choose-k subsets.
+ */
+struct Tseitin {
+    next_var: u32,
+    polarity: bool,
+    clauses: Vec<Clause>,
+    // The variable of a sub-formula and whether its x -> g / g -> x is emitted
+    defs: HashMap<Formula, (Lit, bool, bool)>,
+    // A variable fixed true, for the constants of counters and empty XORs
+    top: Option<Lit>,
+}
+
+impl Tseitin {
+    // Adds f as a constraint, splitting top level conjs and clauses
+    fn assert(&mut self, f: &Formula) {
+        match f {
+            FConj(fvec) => fvec.iter().for_each(|g| self.assert(g)),
+            FDisj(fvec) => {
+                let clause = fvec.iter().map(|g| self.lit(g, true, false)).collect();
+                self.clauses.push(clause);
+            }
+            _ => {
+                let l = self.lit(f, true, false);
+                self.clauses.push(vec![l]);
+            }
+        }
+    }
+
+    // A literal implying f if pos, implied by f if neg (equivalent without polarity)
+    fn lit(&mut self, f: &Formula, pos: bool, neg: bool) -> Lit {
+        let (pos, neg) = if self.polarity {
+            (pos, neg)
+        } else {
+            (true, true)
+        };
+        match f {
+            FVar(x) => return *x as Lit,
+            FNeg(frr) => return -self.lit(frr, neg, pos),
+            FDisj(_) | FConj(_) | FAtMost(..) | FAtLeast(..) | FExactly(..) | FXor(_) => {}
+        }
+        let (x, pos_done, neg_done) = match self.defs.get(f) {
+            Some(&def) => def,
+            None => {
+                self.next_var += 1;
+                (self.next_var as Lit, false, false)
+            }
+        };
+        let (need_pos, need_neg) = (pos && !pos_done, neg && !neg_done);
+        self.defs
+            .insert(f.clone(), (x, pos_done || pos, neg_done || neg));
+        if !need_pos && !need_neg {
+            return x;
+        }
+        let ls: Vec<Lit> = match f {
+            FDisj(fvec) | FConj(fvec) => fvec
+                .iter()
+                .map(|g| self.lit(g, need_pos, need_neg))
+                .collect(),
+            _ => {
+                // The gadget's literal is equivalent to the node, x only
+                // follows it in the directions needed
+                let y = self.gadget(f, need_pos, need_neg);
+                if need_pos {
+                    self.clauses.push(vec![-x, y]);
+                }
+                if need_neg {
+                    self.clauses.push(vec![x, -y]);
                 }
-                res
+                return x;
             }
-        }),
-        FConj(_) => panic!("Why top level conj!"),
+        };
+        match f {
+            FConj(_) => {
+                // x -> l for every l, and (l1 /\ ... /\ ln) -> x
+                if need_pos {
+                    self.clauses.extend(ls.iter().map(|&l| vec![-x, l]));
+                }
+                if need_neg {
+                    let mut clause: Clause = ls.iter().map(|&l| -l).collect();
+                    clause.push(x);
+                    self.clauses.push(clause);
+                }
+            }
+            _ => {
+                // x -> (l1 \/ ... \/ ln), and l -> x for every l
+                if need_pos {
+                    let mut clause = ls.clone();
+                    clause.push(-x);
+                    self.clauses.push(clause);
+                }
+                if need_neg {
+                    self.clauses.extend(ls.iter().map(|&l| vec![x, -l]));
+                }
+            }
+        }
+        x
+    }
+
+    fn fresh(&mut self) -> Lit {
+        self.next_var += 1;
+        self.next_var as Lit
+    }
+
+    fn constant(&mut self, val: bool) -> Lit {
+        let top = match self.top {
+            Some(top) => top,
+            None => {
+                let top = self.fresh();
+                self.clauses.push(vec![top]);
+                self.top = Some(top);
+                top
+            }
+        };
+        if val {
+            top
+        } else {
+            -top
+        }
+    }
+
+    // A fresh x with x <-> a /\ b
+    fn and_gate(&mut self, a: Lit, b: Lit) -> Lit {
+        let x = self.fresh();
+        self.clauses.push(vec![-x, a]);
+        self.clauses.push(vec![-x, b]);
+        self.clauses.push(vec![x, -a, -b]);
+        x
+    }
+
+    fn or_gate(&mut self, a: Lit, b: Lit) -> Lit {
+        -self.and_gate(-a, -b)
+    }
+
+    // A fresh x with x <-> a xor b
+    fn xor_gate(&mut self, a: Lit, b: Lit) -> Lit {
+        let x = self.fresh();
+        self.clauses.push(vec![-x, a, b]);
+        self.clauses.push(vec![-x, -a, -b]);
+        self.clauses.push(vec![x, -a, b]);
+        self.clauses.push(vec![x, a, -b]);
+        x
+    }
+
+    /**
+     * Sequential counter: at_least[j] is a literal equivalent to "at least j
+     * of ls hold", for j in 0..=max. Over the first i literals, at least j
+     * hold iff at least j of the first i - 1 do, or j - 1 of them and the
+     * i-th.
+     */
+    fn counter(&mut self, ls: &[Lit], max: usize) -> Vec<Lit> {
+        let top = self.constant(true);
+        // at_least over the first i literals, for j up to min(i, max)
+        let mut row = vec![top];
+        for &l in ls {
+            let mut next = vec![top];
+            for j in 1..=max.min(row.len()) {
+                let with_l = if j == 1 {
+                    l
+                } else {
+                    self.and_gate(row[j - 1], l)
+                };
+                next.push(match row.get(j) {
+                    Some(&without_l) => self.or_gate(without_l, with_l),
+                    None => with_l,
+                });
+            }
+            row = next;
+        }
+        row.resize(max + 1, -top);
+        row
+    }
+
+    /**
+     * A literal equivalent to a cardinality or XOR node over the literals of
+     * its sub-formulas, taken in the directions x -> g (pos) and g -> x (neg)
+     * need: at least k is monotone in them, at most k antitone
+     */
+    fn gadget(&mut self, f: &Formula, pos: bool, neg: bool) -> Lit {
+        let both = pos || neg;
+        let mut lits = |fvec: &[Formula], pos, neg| -> Vec<Lit> {
+            fvec.iter().map(|g| self.lit(g, pos, neg)).collect()
+        };
+        match f {
+            FAtLeast(k, fvec) => {
+                let ls = lits(fvec, pos, neg);
+                self.counter(&ls, *k as usize)[*k as usize]
+            }
+            FAtMost(k, fvec) => {
+                let ls = lits(fvec, neg, pos);
+                -self.counter(&ls, *k as usize + 1)[*k as usize + 1]
+            }
+            FExactly(k, fvec) => {
+                let ls = lits(fvec, both, both);
+                let at_least = self.counter(&ls, *k as usize + 1);
+                self.and_gate(at_least[*k as usize], -at_least[*k as usize + 1])
+            }
+            // A chain of binary XORs, each one with its own variable
+            FXor(fvec) => {
+                let ls = lits(fvec, both, both);
+                match ls.split_first() {
+                    Some((&first, rest)) => {
+                        rest.iter().fold(first, |acc, &l| self.xor_gate(acc, l))
+                    }
+                    None => self.constant(false),
+                }
+            }
+            _ => unreachable!("Only cardinality and XOR nodes have gadgets"),
+        }
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/**
+ * The Tseitin (Plaisted-Greenbaum if polarity) clauses of f, with fresh
+ * variables from num_vars + 1 on, and the number of variables used
+ */
+fn tseitin(f: &Formula, num_vars: usize, polarity: bool) -> (Vec<Clause>, usize) {
+    let mut enc = Tseitin {
+        next_var: num_vars as u32,
+        polarity,
+        clauses: Vec::new(),
+        defs: HashMap::new(),
+        top: None,
+    };
+    enc.assert(f);
+    (enc.clauses, enc.next_var as usize)
+}
 
-    if args.len() != 2 {
-        eprintln!("Usage: {} <input_file>", args[0]);
-        std::process::exit(-1);
+/**
+ * A model of the negation of f (its DNF by to_dnf, then sat), or None if f is
+ * a tautology (valid)
+ */
+fn formula_counterexample(f: Formula) -> Option<Vec<Lit>> {
+    // Wrapped in a conj like in to_cnf: -f may be a literal
+    sat(to_dnf(FConj(vec![FNeg(Box::new(f))])))
+}
+
+// Whether every assignment satisfies f
+fn is_tautology(f: Formula) -> bool {
+    formula_counterexample(f).is_none()
+}
+
+/**
+ * Ok if every model of f satisfies g (F /\ -G has no model), else Err with a
+ * model of f falsifying g
+ */
+fn entails(f: Formula, g: Formula) -> Result<(), Vec<Lit>> {
+    match sat(to_dnf(FConj(vec![f, FNeg(Box::new(g))]))) {
+        Some(lits) => Err(lits),
+        None => Ok(()),
     }
+}
 
-    let file_name = &args[1];
+/**
+ * Ok if f and g have the same models, else Err with a model of exactly one
+ * of them
+ */
+fn equivalent(f: Formula, g: Formula) -> Result<(), Vec<Lit>> {
+    entails(f.clone(), g.clone())?;
+    entails(g, f)
+}
 
+// A .dnf file as the disjunction of its cubes, anything else as a CNF(+)
+fn read_formula(file_name: &str) -> (Formula, usize) {
     let file = File::open(file_name).expect("Failed to open file!");
-
     let reader = BufReader::new(file);
+    if file_name.ends_with(".dnf") {
+        let (num_vars, cubes) = dnf::parse_dnf(reader);
+        (proc_cubes(cubes), num_vars)
+    } else {
+        let db = cnf::parse_dimacs(reader);
+        let parts = parts_of(&db, false, true, &mut PartStats::default());
+        (FConj(parts), db.num_vars)
+    }
+}
 
-    let mut lines = reader.lines();
-    println!("READ LINES INTO BUFREADER");
-    // READ THE FIRST INFO LINE
-    lines.next();
+// The equiv / entails subcommands: compare the formulas of two files
+fn run_compare(command: &str, file_a: &str, file_b: &str) {
+    let (f, vars_f) = read_formula(file_a);
+    let (g, vars_g) = read_formula(file_b);
+    println!("Welcome to Converter");
+    let (name, res) = if command == "equiv" {
+        ("Equivalent", equivalent(f, g))
+    } else {
+        ("Entails", entails(f, g))
+    };
+    match res {
+        Ok(()) => println!("{name}: true"),
+        Err(lits) => {
+            println!("{name}: false");
+            let model = cnf::model_from_lits(&lits, vars_f.max(vars_g));
+            println!("{}", cnf::model_line(&model));
+        }
+    }
+}
+
+// The and / or / xor / not subcommands: combine the compiled DNFs of files
+fn run_algebra(command: &str, in_files: &[String], out_name: &str) {
+    let (dnfs, num_vars): (Vec<dnf::Dnf>, Vec<usize>) = in_files
+        .iter()
+        .map(|file_name| {
+            let (f, num_vars) = read_formula(file_name);
+            (dnf::Dnf::new(cubes(to_dnf(f))), num_vars)
+        })
+        .unzip();
+    println!("Welcome to Converter");
+    let result = match (command, &dnfs[..]) {
+        ("and", [a, b]) => a & b,
+        ("or", [a, b]) => a | b,
+        ("xor", [a, b]) => a ^ b,
+        ("not", [a]) => !a,
+        _ => unreachable!("checked in main"),
+    };
+    println!("Cubes: {}", result.cubes().len());
+    let out = File::create(out_name).expect("Failed to create DNF file!");
+    let num_vars = num_vars.into_iter().max().unwrap_or(0);
+    dnf::write_dnf(result.cubes(), num_vars, BufWriter::new(out))
+        .expect("Failed to write DNF file!");
+    println!("Wrote {out_name}");
+}
 
-    let ret_form_vec: Vec<Formula> = lines.into_iter().map(|line| proc_line(line)).collect();
-    // par_bridge().map(|line| proc_line(line)).collect();
+// The to-cnf subcommand: write the formula of a file as a plain DIMACS CNF
+fn run_to_cnf(in_file: &str, out_name: &str, encoding: Option<&str>) {
+    let (f, num_vars) = read_formula(in_file);
     println!("Welcome to Converter");
-    let formula = to_dnf(FConj(ret_form_vec));
-    // println!(
-    //     "DONE: DISJ_BELOW_CONJ = {}; DISJ_BELOW_NEG = {}; DNF_SPEC = {}",
-    //     disj_below_neg(formula.clone(), false),
-    //     disj_below_conj(formula.clone(), false),
-    //     dnf_spec(formula.clone(), false, false, false)
-    // );
+    let (clauses, total_vars) = match encoding {
+        None => (clauses(to_cnf(f)), num_vars),
+        Some(encoding) => tseitin(&f, num_vars, encoding == "--pg"),
+    };
+    println!(
+        "CNF: {} clauses, {} auxiliary variables",
+        clauses.len(),
+        total_vars - num_vars
+    );
+    let db = ClauseDb {
+        num_vars: total_vars,
+        clauses,
+        ..ClauseDb::default()
+    };
+    let out = File::create(out_name).expect("Failed to create CNF file!");
+    cnf::write_dimacs(&db, BufWriter::new(out)).expect("Failed to write CNF file!");
+    println!("Wrote {out_name}");
+}
+
+// DNF input: check that the cubes cover every assignment
+fn run_dnf(file_name: &str) {
+    let file = File::open(file_name).expect("Failed to open file!");
+    let (num_vars, cubes) = dnf::parse_dnf(BufReader::new(file));
+    println!("Welcome to Converter");
+    if dnf::is_tautology(&cubes) {
+        println!("Tautology: true");
+        return;
+    }
+    // Not valid, so the search stops at the first uncovered branch
+    let lits = dnf::counterexample(&cubes).expect("not a tautology");
+    println!("Tautology: false");
+    println!(
+        "{}",
+        cnf::model_line(&cnf::model_from_lits(&lits, num_vars))
+    );
+}
 
+// OPB input: cross the cubes of every constraint, then minimize the objective
+fn run_opb(file_name: &str) {
+    let file = File::open(file_name).expect("Failed to open file!");
+    let problem = opb::parse_opb(BufReader::new(file));
+    println!("Welcome to Converter");
+    let formula = FConj(
+        problem
+            .constraints
+            .iter()
+            .map(|c| proc_cubes(opb::constraint_cubes(c)))
+            .collect(),
+    );
+    let cubes = cubes(to_dnf(formula));
+    let best = match &problem.objective {
+        Some(obj) => dnf::optimize(&cubes, &obj.costs(), problem.num_vars)
+            .map(|(cost, model)| (obj.offset + cost, model)),
+        None => cubes
+            .first()
+            .map(|cube| (0, cnf::model_from_lits(cube, problem.num_vars))),
+    };
+    match best {
+        Some((cost, model)) => {
+            if problem.objective.is_some() {
+                println!("o {cost}");
+                println!("s OPTIMUM FOUND");
+            } else {
+                println!("s SATISFIABLE");
+            }
+            println!("{}", opb::model_line(&model));
+        }
+        None => println!("s UNSATISFIABLE"),
+    }
+}
+
+// WCNF input: the cheapest completion of every cube of the hard clauses
+fn run_wcnf(file_name: &str) {
+    let file = File::open(file_name).expect("Failed to open file!");
+    let problem = wcnf::parse_wcnf(BufReader::new(file));
+    println!("Welcome to Converter");
+    let cubes = cubes(dnf_of(&problem.hard, false, true));
+    match wcnf::solve(&cubes, &problem.soft, problem.hard.num_vars) {
+        Some((cost, model)) => {
+            println!("o {cost}");
+            println!("s OPTIMUM FOUND");
+            println!("{}", wcnf::model_line(&model));
+        }
+        None => println!("s UNSATISFIABLE"),
+    }
+}
+
+// The flags after the input file; each mode takes the ones it understands
+struct Flags {
+    prog: String,
+    rest: Vec<String>,
+}
+
+impl Flags {
+    fn has(&self, name: &str) -> bool {
+        self.rest.iter().any(|arg| arg == name)
+    }
+
+    // Whether the switch was given
+    fn switch(&mut self, name: &str) -> bool {
+        match self.rest.iter().position(|arg| arg == name) {
+            Some(i) => {
+                self.rest.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // The argument following any of the names, if one of them was given
+    fn value(&mut self, names: &[&str]) -> Option<String> {
+        let i = self
+            .rest
+            .iter()
+            .position(|arg| names.contains(&arg.as_str()))?;
+        if i + 1 == self.rest.len() {
+            usage(&self.prog);
+        }
+        self.rest.remove(i);
+        Some(self.rest.remove(i))
+    }
+
+    fn parsed<T: std::str::FromStr>(&mut self, name: &str) -> Option<T> {
+        let value = self.value(&[name])?;
+        Some(value.parse().unwrap_or_else(|_| usage(&self.prog)))
+    }
+
+    // Any flag left over does not belong to the mode
+    fn finish(self) {
+        if !self.rest.is_empty() {
+            usage(&self.prog);
+        }
+    }
+}
+
+// A mode of the CNF input, given the input file and the flags after it
+type Mode = fn(&str, Flags);
+
+// Where the progress messages go: stderr for the modes printing a result
+// meant for other programs on stdout
+#[derive(Debug, Clone, Copy)]
+enum Log {
+    Stdout,
+    Stderr,
+}
+
+impl Log {
+    fn line(self, msg: impl std::fmt::Display) {
+        match self {
+            Log::Stdout => println!("{msg}"),
+            Log::Stderr => eprintln!("{msg}"),
+        }
+    }
+}
+
+// How the CNF is compiled into cubes: --components, --amo, --no-gauss
+struct EngineOpts {
+    split_components: bool,
+    detect_amo: bool,
+    gauss: bool,
+    log: Log,
+}
+
+impl EngineOpts {
+    fn parse(flags: &mut Flags) -> EngineOpts {
+        EngineOpts {
+            split_components: flags.switch("--components"),
+            detect_amo: flags.switch("--amo"),
+            gauss: !flags.switch("--no-gauss"),
+            log: Log::Stdout,
+        }
+    }
+
+    // The components of db and the parts to cross for each
+    fn compile(&self, db: ClauseDb) -> (Vec<ClauseDb>, Vec<Vec<Formula>>) {
+        let comps = if self.split_components {
+            let comps = components::split(&db);
+            self.log
+                .line(format_args!("Split into {} components", comps.len()));
+            comps
+        } else {
+            vec![db]
+        };
+        let mut stats = PartStats::default();
+        let parts = comps
+            .iter()
+            .map(|comp| parts_of(comp, self.detect_amo, self.gauss, &mut stats))
+            .collect();
+        let stats = stats.to_string();
+        if !stats.is_empty() {
+            self.log.line(stats.trim_end());
+        }
+        self.log.line("Welcome to Converter");
+        (comps, parts)
+    }
+}
+
+// --condition and --forget, applied to the cubes before a query
+struct Restrict {
+    condition: Option<Vec<Lit>>,
+    forget: Option<Vec<usize>>,
+}
+
+impl Restrict {
+    fn parse(flags: &mut Flags) -> Restrict {
+        let condition: Option<Vec<Lit>> = flags.value(&["--condition"]).map(|list| {
+            let mut lits: Vec<Lit> = parse_list(&list, &flags.prog);
+            lits.sort_unstable();
+            lits.dedup();
+            lits
+        });
+        let forget: Option<Vec<usize>> = flags
+            .value(&["--forget"])
+            .map(|list| parse_list(&list, &flags.prog));
+        if condition
+            .as_ref()
+            .is_some_and(|lits| lits.contains(&0) || lits.iter().any(|l| lits.contains(&-l)))
+            || forget.as_ref().is_some_and(|vars| vars.contains(&0))
+        {
+            usage(&flags.prog);
+        }
+        Restrict { condition, forget }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.condition.is_none() && self.forget.is_none()
+    }
+
+    // The cubes of a component's DNF, after --condition and --forget
+    fn cubes(&self, parts: &[Formula]) -> Vec<Cube> {
+        let mut cubes = cubes(to_dnf(FConj(parts.to_vec())));
+        if let Some(lits) = &self.condition {
+            cubes = dnf::condition(&cubes, lits);
+        }
+        if let Some(vars) = &self.forget {
+            cubes = dnf::forget(&cubes, vars);
+        }
+        cubes
+    }
+
+    // The literals of a witness, with the condition's added back: the cubes
+    // leave them free, but every model asked for makes them true
+    fn witness(&self, mut lits: Vec<Lit>) -> Vec<Lit> {
+        lits.extend(self.condition.iter().flatten());
+        lits
+    }
+}
+
+// The CNF(+) of a file, with the line each clause was read from
+fn read_cnf(file_name: &str, log: Log) -> (ClauseDb, Vec<usize>) {
+    let file = File::open(file_name).expect("Failed to open file!");
+    let reader = BufReader::new(file);
+    log.line("READ LINES INTO BUFREADER");
+    cnf::parse_dimacs_with_lines(reader)
+}
+
+fn require_plain(db: &ClauseDb, what: &str) {
+    if !db.is_plain_cnf() {
+        eprintln!("{what} do not support cardinality or XOR constraints");
+        std::process::exit(-1);
+    }
+}
+
+// The passes given to -p, none by default
+fn parse_passes(flags: &mut Flags) -> Vec<preprocess::Pass> {
+    flags
+        .value(&["-p", "--preprocess"])
+        .map(|list| preprocess::parse_passes(&list))
+        .unwrap_or_default()
+}
+
+// Runs the passes, an empty clause standing in for a CNF found UNSAT
+fn preprocess_cnf(
+    db: ClauseDb,
+    passes: &[preprocess::Pass],
+) -> (ClauseDb, preprocess::Reconstruction) {
+    if passes.is_empty() {
+        return (db, preprocess::Reconstruction::default());
+    }
+    require_plain(&db, "Preprocessing passes");
+    let num_clauses = db.clauses.len();
+    let mut pass_stats = Vec::new();
+    let preprocessed = preprocess::preprocess(db, passes, &mut pass_stats);
+    for stats in &pass_stats {
+        println!("{stats}");
+    }
+    let (db, rec) = match preprocessed {
+        Some((db, rec)) => (db, rec),
+        None => {
+            println!("Preprocessing found a conflict");
+            // An empty clause keeps the written CNF unsatisfiable
+            let unsat = cnf::ClauseDb {
+                clauses: vec![vec![]],
+                ..cnf::ClauseDb::default()
+            };
+            (unsat, preprocess::Reconstruction::default())
+        }
+    };
+    println!(
+        "Preprocessed: {} -> {} clauses, {} reconstruction entries",
+        num_clauses,
+        db.clauses.len(),
+        rec.stack.len()
+    );
+    (db, rec)
+}
+
+// --write-cnf: write the preprocessed CNF, and with --write-rec its stack
+fn run_write_cnf(file_name: &str, mut flags: Flags) {
+    let passes = parse_passes(&mut flags);
+    let out_name = flags
+        .value(&["--write-cnf"])
+        .unwrap_or_else(|| usage(&flags.prog));
+    let rec_name = flags.value(&["--write-rec"]);
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    let (db, rec) = preprocess_cnf(db, &passes);
+    let (compacted, var_map) = cnf::compact(&db);
+    let out = File::create(&out_name).expect("Failed to create CNF file!");
+    cnf::write_dimacs(&compacted, BufWriter::new(out)).expect("Failed to write CNF file!");
+    println!("Wrote {out_name}");
+    if let Some(rec_name) = rec_name {
+        let out = File::create(&rec_name).expect("Failed to create reconstruction file!");
+        rec.write(&var_map, BufWriter::new(out))
+            .expect("Failed to write reconstruction file!");
+        println!("Wrote {rec_name}");
+    }
+}
+
+// --mus: a minimal unsatisfiable subset of the clauses
+fn run_mus(file_name: &str, mut flags: Flags) {
+    flags.switch("--mus");
+    let engine = EngineOpts::parse(&mut flags);
+    flags.finish();
+    let (db, clause_lines) = read_cnf(file_name, Log::Stdout);
+    // The cardinality constraints and XORs stay in every checked subset
+    let mut checks = 0;
+    let all: Vec<usize> = (0..db.clauses.len()).collect();
+    let core = mus::quickxplain(&all, |subset| {
+        checks += 1;
+        let sub = ClauseDb {
+            num_vars: db.num_vars,
+            clauses: subset.iter().map(|&i| db.clauses[i].clone()).collect(),
+            cards: db.cards.clone(),
+            xors: db.xors.clone(),
+        };
+        let comps = if engine.split_components {
+            components::split(&sub)
+        } else {
+            vec![sub]
+        };
+        comps
+            .par_iter()
+            .all(|comp| sat(dnf_of(comp, engine.detect_amo, engine.gauss)).is_some())
+    });
+    match core {
+        Some(mut core) => {
+            core.sort_unstable();
+            println!("SAT: false");
+            println!(
+                "MUS: {} of {} clauses ({checks} checks)",
+                core.len(),
+                db.clauses.len()
+            );
+            for i in core {
+                let lits: String = db.clauses[i].iter().map(|l| format!("{l} ")).collect();
+                println!("clause {} (line {}): {lits}0", i + 1, clause_lines[i]);
+            }
+        }
+        None => println!("SAT: true"),
+    }
+}
+
+// --explain: what satisfies each part of the witness, and the parts forcing literals
+fn run_explain(file_name: &str, mut flags: Flags) {
+    flags.switch("--explain");
+    let engine = EngineOpts::parse(&mut flags);
+    flags.finish();
+    let (db, clause_lines) = read_cnf(file_name, Log::Stdout);
+    let num_vars = db.num_vars;
+    // Components and AMO extraction keep the clauses they do not replace as is
+    let clause_idx: HashMap<&Clause, usize> = db
+        .clauses
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, clause)| (clause, i))
+        .collect();
+    let (_, comp_parts) = engine.compile(db.clone());
+    let part_name = |part: &Formula| match part {
+        FDisj(fvec) if fvec.iter().all(|f| matches!(f, FVar(_) | FNeg(_))) => {
+            let clause: Clause = fvec.iter().map(lit_of).collect();
+            match clause_idx.get(&clause) {
+                Some(&i) => format!("clause {} (line {})", i + 1, clause_lines[i]),
+                None => format!("clause {}", lits_text(&clause)),
+            }
+        }
+        FAtMost(k, fvec) => format!("at most {k} of {}", formulas_text(fvec)),
+        FAtLeast(k, fvec) => format!("at least {k} of {}", formulas_text(fvec)),
+        FExactly(k, fvec) => format!("exactly {k} of {}", formulas_text(fvec)),
+        FXor(fvec) => format!("xor of {}", formulas_text(fvec)),
+        _ => String::from("the XOR system"),
+    };
+    // For every component its parts' names and DNFs, and its traced cubes
+    let traced: Vec<(Vec<String>, Vec<Formula>, Vec<provenance::TracedCube>)> = comp_parts
+        .into_iter()
+        .map(|parts| {
+            let names = parts.iter().map(part_name).collect();
+            let dnfs: Vec<Formula> = parts.into_par_iter().map(to_dnf).collect();
+            let cubes = traced_cubes(dnfs.clone());
+            (names, dnfs, cubes)
+        })
+        .collect();
+    if traced.iter().any(|(_, _, cubes)| cubes.is_empty()) {
+        println!("SAT: false");
+        return;
+    }
+    println!("SAT: true");
+    let witness: Vec<Lit> = traced
+        .iter()
+        .flat_map(|(_, _, cubes)| cubes[0].lits.iter().copied())
+        .collect();
+    println!(
+        "{}",
+        cnf::model_line(&cnf::model_from_lits(&witness, num_vars))
+    );
+    for (names, dnfs, cubes) in &traced {
+        for (i, &j) in cubes[0].chosen.iter().enumerate() {
+            let pick = lits_text(&disjunct_lits(&dnfs[i], j));
+            println!("{}: satisfied by {pick}", names[i]);
+        }
+    }
+    let forced: Vec<String> = traced
+        .iter()
+        .flat_map(|(names, dnfs, cubes)| {
+            provenance::forced(cubes).into_iter().map(|(i, j)| {
+                let pick = lits_text(&disjunct_lits(&dnfs[i], j));
+                format!("{}: forces {pick}", names[i])
+            })
+        })
+        .collect();
+    let num_cubes: usize = traced.iter().map(|(_, _, cubes)| cubes.len()).sum();
+    println!("Forced: {} parts over {num_cubes} cubes", forced.len());
+    for line in forced {
+        println!("{line}");
+    }
+}
+
+// The literals of the disjunct a traced cube took from a DNF
+fn disjunct_lits(dnf: &Formula, j: usize) -> Vec<Lit> {
+    let disjunct = match dnf {
+        FDisj(fvec) => &fvec[j],
+        _ => dnf,
+    };
+    match disjunct {
+        FConj(fvec) => fvec.iter().map(lit_of).collect(),
+        _ => vec![lit_of(disjunct)],
+    }
+}
+
+fn lits_text(lits: &[Lit]) -> String {
+    let lits: Vec<String> = lits.iter().map(|l| l.to_string()).collect();
+    lits.join(" ")
+}
+
+fn formulas_text(fvec: &[Formula]) -> String {
+    lits_text(&fvec.iter().map(lit_of).collect::<Vec<Lit>>())
+}
+
+// --proof: a DRAT (or LRAT) refutation if the CNF is UNSAT
+fn run_proof(file_name: &str, mut flags: Flags) {
+    let proof_name = flags
+        .value(&["--proof"])
+        .unwrap_or_else(|| usage(&flags.prog));
+    let lrat = flags.switch("--lrat");
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    require_plain(&db, "Proofs");
+    match proof::refute(&db.clauses, db.num_vars) {
+        Ok(lits) => {
+            println!("SAT: true");
+            let model = cnf::model_from_lits(&lits, db.num_vars);
+            println!("{}", cnf::model_line(&model));
+        }
+        Err(steps) => {
+            println!("SAT: false");
+            let out = File::create(&proof_name).expect("Failed to create proof file!");
+            let out = BufWriter::new(out);
+            if lrat {
+                proof::write_lrat(&steps, out)
+            } else {
+                proof::write_drat(&steps, out)
+            }
+            .expect("Failed to write proof file!");
+            println!("Proof: {} steps, wrote {proof_name}", steps.len());
+        }
+    }
+}
+
+// --check-proof: verify a DRAT (or LRAT) refutation, exit 1 if it fails
+fn run_check_proof(file_name: &str, mut flags: Flags) {
+    let check_name = flags
+        .value(&["--check-proof"])
+        .unwrap_or_else(|| usage(&flags.prog));
+    let lrat = flags.switch("--lrat");
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    require_plain(&db, "Proofs");
+    let text = std::fs::read_to_string(&check_name).expect("Failed to read proof file!");
+    let checked = if lrat {
+        proof::check_lrat(&db.clauses, &text)
+    } else {
+        proof::check_drat(&db.clauses, &text)
+    };
+    match checked {
+        Ok(()) => println!("Proof: VERIFIED"),
+        Err(reason) => {
+            println!("Proof: FAILED ({reason})");
+            std::process::exit(1);
+        }
+    }
+}
+
+// --tautology: whether every assignment satisfies the input
+fn run_tautology(file_name: &str, mut flags: Flags) {
+    flags.switch("--tautology");
+    let engine = EngineOpts::parse(&mut flags);
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    let num_vars = db.num_vars;
+    let (_, comp_parts) = engine.compile(db);
+    // A conjunction is valid iff every conjunct is: a CNF only if every clause
+    // is, but cards and XORs need not be
+    if comp_parts
+        .par_iter()
+        .all(|parts| is_tautology(FConj(parts.clone())))
+    {
+        println!("Tautology: true");
+        return;
+    }
+    let lits = comp_parts
+        .into_par_iter()
+        .find_map_any(|parts| formula_counterexample(FConj(parts)))
+        .expect("not a tautology");
+    println!("Tautology: false");
+    println!(
+        "{}",
+        cnf::model_line(&cnf::model_from_lits(&lits, num_vars))
+    );
+}
+
+// --count: the number of models
+fn run_count(file_name: &str, mut flags: Flags) {
+    flags.switch("--count");
+    let engine = EngineOpts::parse(&mut flags);
+    let restrict = Restrict::parse(&mut flags);
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    let num_vars = db.num_vars;
+    let (comps, comp_parts) = engine.compile(db);
+    // Multiply the component counts instead of crossing their DNFs
+    let used: usize = comps.iter().map(components::num_vars_used).sum();
+    let models = comps
+        .par_iter()
+        .zip(&comp_parts)
+        .map(|(comp, parts)| {
+            let cubes = restrict.cubes(parts);
+            dnf::count_models(&cubes, components::num_vars_used(comp))
+        })
+        .reduce(BigUint::one, |a, b| &a * &b);
+    println!("Models: {}", models.mul_pow2(num_vars - used));
+}
+
+// --backbone: the literals true in every model
+fn run_backbone(file_name: &str, mut flags: Flags) {
+    flags.switch("--backbone");
+    let engine = EngineOpts::parse(&mut flags);
+    let restrict = Restrict::parse(&mut flags);
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    let (_, comp_parts) = engine.compile(db);
+    // Components share no variables, so the backbone is the union of theirs
+    let backbone: Option<Vec<Vec<Lit>>> = comp_parts
+        .par_iter()
+        .map(|parts| dnf::backbone(&restrict.cubes(parts)))
+        .collect();
+    match backbone {
+        Some(backbone) => {
+            let mut lits = backbone.concat();
+            lits.sort_unstable_by_key(|&l| cnf::var(l));
+            println!("Backbone: {} literals", lits.len());
+            let line: String = lits.iter().map(|l| format!(" {l}")).collect();
+            println!("b{line} 0");
+        }
+        None => println!("SAT: false"),
+    }
+}
+
+// --marginals: P(v = true) of every variable under the literal weights, as JSON
+fn run_marginals(file_name: &str, mut flags: Flags) {
+    flags.switch("--marginals");
+    let weights_file = flags.value(&["--weights"]);
+    let samples: Option<u64> = flags.parsed("--samples");
+    let seed: Option<u64> = flags.parsed("--seed");
+    if seed.is_some() && samples.is_none() {
+        usage(&flags.prog);
+    }
+    let mut engine = EngineOpts::parse(&mut flags);
+    let restrict = Restrict::parse(&mut flags);
+    flags.finish();
+    // stdout is only the JSON, so it can be piped into other programs
+    engine.log = Log::Stderr;
+    let (db, _) = read_cnf(file_name, engine.log);
+    let num_vars = db.num_vars;
+    let (comps, comp_parts) = engine.compile(db);
+    let weights = match weights_file {
+        Some(weights_file) => {
+            let file = File::open(&weights_file).expect("Failed to open weights file!");
+            marginals::parse_weights(BufReader::new(file))
+        }
+        None => marginals::Weights::default(),
+    };
+    let results: Vec<(Option<Vec<f64>>, u64)> = comp_parts
+        .par_iter()
+        .map(|parts| {
+            let cubes = restrict.cubes(parts);
+            match samples {
+                Some(samples) => {
+                    marginals::monte_carlo(&cubes, num_vars, &weights, samples, seed.unwrap_or(0))
+                }
+                None => marginals::exact(&cubes, num_vars, &weights),
+            }
+        })
+        .collect();
+    for (_, n) in &results {
+        match samples {
+            Some(samples) => engine
+                .log
+                .line(format_args!("Marginals: {n} of {samples} samples accepted")),
+            None => engine
+                .log
+                .line(format_args!("Marginals: {n} disjoint cubes")),
+        }
+    }
+    let per_comp: Option<Vec<Vec<f64>>> = results.into_iter().map(|(m, _)| m).collect();
+    match per_comp {
+        Some(per_comp) => {
+            // A variable depends only on the component mentioning it
+            let mut merged: Vec<f64> = (0..=num_vars).map(|v| weights.prob(v as Lit)).collect();
+            merged[0] = 0.0;
+            for (comp, comp_marginals) in comps.iter().zip(&per_comp) {
+                for &l in comp.constraint_lits().flatten() {
+                    merged[cnf::var(l)] = comp_marginals[cnf::var(l)];
+                }
+            }
+            let method = if samples.is_some() {
+                "monte-carlo"
+            } else {
+                "exact"
+            };
+            println!("{}", marginals::to_json(&merged, method));
+        }
+        None => {
+            engine.log.line("SAT: false");
+            println!("null");
+        }
+    }
+}
+
+// --costs: a minimum cost model
+fn run_costs(file_name: &str, mut flags: Flags) {
+    let costs_file = flags
+        .value(&["--costs"])
+        .unwrap_or_else(|| usage(&flags.prog));
+    let engine = EngineOpts::parse(&mut flags);
+    let restrict = Restrict::parse(&mut flags);
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    let num_vars = db.num_vars;
+    let (_, comp_parts) = engine.compile(db);
+    let file = File::open(&costs_file).expect("Failed to open costs file!");
+    let costs = dnf::parse_costs(BufReader::new(file));
+    // Components share no variables, so their excess costs add up
+    let best: Option<Vec<(i64, Cube)>> = comp_parts
+        .par_iter()
+        .map(|parts| {
+            let cubes = restrict.cubes(parts);
+            dnf::best_cube(&cubes, &costs).map(|(excess, cube)| (excess, cube.clone()))
+        })
+        .collect();
+    match best {
+        Some(best) => {
+            let excess: i64 = best.iter().map(|(excess, _)| excess).sum();
+            // The condition's literals are set outside the cubes, so they are
+            // paid for here
+            let fixed = restrict.witness(Vec::new());
+            let excess = excess + fixed.iter().map(|&l| costs.excess(l)).sum::<i64>();
+            let lits: Vec<Lit> = best
+                .into_iter()
+                .flat_map(|(_, cube)| cube)
+                .chain(fixed)
+                .collect();
+            println!("Cost: {}", costs.base() + excess);
+            println!("{}", cnf::model_line(&costs.complete(&lits, num_vars)));
+        }
+        None => println!("SAT: false"),
+    }
+}
+
+// --lexmin / --lexmax: the lexicographically smallest or largest model
+fn run_lex(file_name: &str, mut flags: Flags) {
+    let max = flags.switch("--lexmax");
+    flags.switch("--lexmin");
+    let order_file = flags.value(&["--order"]);
+    let lazy = flags.switch("--lazy");
+    let engine = EngineOpts::parse(&mut flags);
+    let restrict = Restrict::parse(&mut flags);
+    if lazy && !restrict.is_empty() {
+        usage(&flags.prog);
+    }
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    let num_vars = db.num_vars;
+    let (_, comp_parts) = engine.compile(db);
+    let order = match order_file {
+        Some(order_file) => {
+            let file = File::open(&order_file).expect("Failed to open order file!");
+            lex::parse_order(BufReader::new(file))
+        }
+        None => Vec::new(),
+    };
+    let order = lex::full_order(&order, num_vars);
+    let model = if lazy {
+        let parts: Vec<Vec<Cube>> = comp_parts
+            .into_iter()
+            .flatten()
+            .collect::<Vec<Formula>>()
+            .into_par_iter()
+            .map(|part| cubes(to_dnf(FConj(vec![part]))))
+            .collect();
+        lex::lazy_search(&parts, &order, max)
+    } else {
+        // Components share no variables, their best cubes make the best model
+        let best: Option<Vec<Cube>> = comp_parts
+            .par_iter()
+            .map(|parts| {
+                let cubes = restrict.cubes(parts);
+                lex::best_cube(&cubes, &order, max).cloned()
+            })
+            .collect();
+        best.map(|best| lex::complete(&restrict.witness(best.concat()), num_vars, max))
+    };
+    match model {
+        Some(model) => {
+            println!("SAT: true");
+            println!("{}", cnf::model_line(&model));
+        }
+        None => println!("SAT: false"),
+    }
+}
+
+// The default mode: check SAT and print a witness
+fn run_sat(file_name: &str, mut flags: Flags) {
+    let passes = parse_passes(&mut flags);
+    let engine = EngineOpts::parse(&mut flags);
+    let restrict = Restrict::parse(&mut flags);
+    // Preprocessing only preserves satisfiability, not the set of models
+    if !passes.is_empty() && !restrict.is_empty() {
+        usage(&flags.prog);
+    }
+    flags.finish();
+    let (db, _) = read_cnf(file_name, Log::Stdout);
+    let num_vars = db.num_vars;
+    let (db, rec) = preprocess_cnf(db, &passes);
+    let (_, comp_parts) = engine.compile(db);
     println!("Checking SAT");
-    let sat = sat(formula);
-    println!("SAT: {sat}")
+    // Components share no variables, so their witnesses never conflict
+    let witness: Option<Vec<Vec<Lit>>> = comp_parts
+        .par_iter()
+        .map(|parts| {
+            if restrict.is_empty() {
+                sat(to_dnf(FConj(parts.clone())))
+            } else {
+                restrict.cubes(parts).into_iter().next()
+            }
+        })
+        .collect();
+    match witness {
+        Some(witness) => {
+            println!("SAT: true");
+            let mut model = cnf::model_from_lits(&restrict.witness(witness.concat()), num_vars);
+            rec.extend(&mut model);
+            println!("{}", cnf::model_line(&model));
+        }
+        None => println!("SAT: false"),
+    }
+}
+
+// A comma separated list of numbers, as given to --condition and --forget
+fn parse_list<T: std::str::FromStr>(list: &str, prog: &str) -> Vec<T> {
+    list.split(',')
+        .map(|ele| ele.trim().parse().unwrap_or_else(|_| usage(prog)))
+        .collect()
+}
+
+fn usage(prog: &str) -> ! {
+    eprintln!(
+        "Usage: {prog} <input_file> [-p|--preprocess <pass,...>] [--write-cnf <file> [--write-rec <file>]]
+                    [--components] [--count | --backbone | --costs <file>
+                     | --lexmin | --lexmax [--order <file>] [--lazy]
+                     | --marginals [--weights <file>] [--samples <n> [--seed <n>]] | --mus | --explain
+                     | --proof <file> | --check-proof <file> | --tautology] [--lrat]
+                    [--condition <lit,...>] [--forget <var,...>] [--amo] [--no-gauss]
+  passes: units, pure, simplify, bve[=<growth>], equiv, bce, probe
+  --write-cnf   stop after preprocessing and write the reduced CNF (variables renumbered)
+  --write-rec   with --write-cnf, also write the model reconstruction file
+  --components  solve the variable-disjoint parts of the CNF independently
+  --count       count the models instead of finding one (no preprocessing)
+  --backbone    list the literals true in every model (no preprocessing)
+  --costs       find a minimum cost model, the file has \"<lit> <cost>\" lines (no preprocessing)
+  --lexmin      find the lexicographically smallest model (no preprocessing), --lexmax the largest
+  --order       the variable order for --lexmin/--lexmax (listed variables first, then the rest)
+  --lazy        search --lexmin/--lexmax in variable order instead of crossing the whole DNF
+  --marginals   print P(v = true) of every variable as JSON (no preprocessing)
+  --weights     literal weights for --marginals, \"<lit> <weight>\" lines (default 1)
+  --samples     estimate --marginals from that many Monte Carlo samples instead of exactly
+  --mus         if UNSAT, report a minimal unsatisfiable subset of the clauses (no preprocessing)
+  --explain     report what the witness takes from each clause (or group, constraint), and
+                which of them force literals in every cube (no preprocessing)
+  --proof       if UNSAT, write a DRAT refutation of the clauses (plain CNF, no preprocessing)
+  --check-proof check a DRAT refutation of the clauses (RUP steps only), exit 1 if it fails
+  --lrat        write or check LRAT instead of DRAT
+  --tautology   check that every assignment satisfies the input, else print one that does not
+  --condition   condition the DNF on the literals before any DNF query or SAT check
+  --forget      existentially quantify the variables out of the DNF (after --condition);
+                both leave those variables free and refuse preprocessing, --lazy and the
+                --mus, --explain, proof and --tautology modes
+  --amo         detect at-most-one/exactly-one groups and expand each as a whole
+  --no-gauss    expand each XOR constraint on its own instead of Gaussian elimination
+  input may be DIMACS CNF or MiniCard CNF+ (\"1 -2 3 <= 2\", \">=\", \"=\" lines)
+  and may contain CryptoMiniSat XOR lines (\"x1 -2 3 0\")
+  an .opb input is solved as a pseudo-Boolean problem (minimizing its objective), without options
+  a .wcnf input is solved as a weighted MaxSAT problem, without options
+  a .dnf input (\"p dnf\" header, one cube per line) is checked for being a tautology
+       {prog} equiv|entails <file_a> <file_b>
+  check that the two CNF(+)/.dnf files have the same models, or that every model of
+  file_a is one of file_b, else print a model telling them apart
+       {prog} and|or|xor <file_a> <file_b> <out.dnf>  |  {prog} not <file> <out.dnf>
+  compile the CNF(+)/.dnf files to DNF and write the result of the operation as a .dnf
+       {prog} to-cnf <file> <out.cnf> [--tseitin | --pg]
+  write the CNF(+)/.dnf file as plain DIMACS CNF, by distributing (the dual of the DNF
+  conversion) or with auxiliary variables (Tseitin, or Plaisted-Greenbaum with --pg)"
+    );
+    std::process::exit(-1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        usage(&args[0]);
+    }
+    if args[1] == "equiv" || args[1] == "entails" {
+        if args.len() != 4 {
+            usage(&args[0]);
+        }
+        run_compare(&args[1], &args[2], &args[3]);
+        return;
+    }
+    let arity = match args[1].as_str() {
+        "and" | "or" | "xor" => Some(2),
+        "not" => Some(1),
+        _ => None,
+    };
+    if let Some(arity) = arity {
+        if args.len() != arity + 3 {
+            usage(&args[0]);
+        }
+        run_algebra(&args[1], &args[2..2 + arity], &args[2 + arity]);
+        return;
+    }
+    if args[1] == "to-cnf" {
+        let encoding = match args.get(4).map(String::as_str) {
+            None => None,
+            Some(flag @ ("--tseitin" | "--pg")) if args.len() == 5 => Some(flag),
+            _ => usage(&args[0]),
+        };
+        if args.len() < 4 {
+            usage(&args[0]);
+        }
+        run_to_cnf(&args[2], &args[3], encoding);
+        return;
+    }
+    if args[1].ends_with(".opb") {
+        if args.len() > 2 {
+            usage(&args[0]);
+        }
+        run_opb(&args[1]);
+        return;
+    }
+    if args[1].ends_with(".dnf") {
+        if args.len() > 2 {
+            usage(&args[0]);
+        }
+        run_dnf(&args[1]);
+        return;
+    }
+    if args[1].ends_with(".wcnf") {
+        if args.len() > 2 {
+            usage(&args[0]);
+        }
+        run_wcnf(&args[1]);
+        return;
+    }
+
+    // Each mode is selected by its flag and takes the other flags it allows
+    let modes: [(&str, Mode); 12] = [
+        ("--write-cnf", run_write_cnf),
+        ("--mus", run_mus),
+        ("--explain", run_explain),
+        ("--proof", run_proof),
+        ("--check-proof", run_check_proof),
+        ("--tautology", run_tautology),
+        ("--count", run_count),
+        ("--backbone", run_backbone),
+        ("--marginals", run_marginals),
+        ("--costs", run_costs),
+        ("--lexmin", run_lex),
+        ("--lexmax", run_lex),
+    ];
+    let flags = Flags {
+        prog: args[0].clone(),
+        rest: args[2..].to_vec(),
+    };
+    let mut chosen = modes.iter().filter(|(flag, _)| flags.has(flag));
+    let run = match (chosen.next(), chosen.next()) {
+        (None, _) => run_sat,
+        (Some(&(_, run)), None) => run,
+        _ => usage(&args[0]),
+    };
+    run(&args[1], flags);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::testing::Lcg;
+
+    // The cubes of f's DNF, sorted
+    fn cube_set(f: Formula) -> Vec<Cube> {
+        let mut cubes = dnf::normalize(cubes(to_dnf(FConj(vec![f]))));
+        cubes.sort_unstable();
+        cubes
+    }
+
+    #[test]
+    fn conjs_below_a_disj_stay_cubes() {
+        // (1 /\ 2 \/ -1) /\ 3
+        let f = FConj(vec![
+            FDisj(vec![FConj(vec![FVar(1), FVar(2)]), proc_lit(-1)]),
+            FVar(3),
+        ]);
+        assert_eq!(cube_set(f), vec![vec![-1, 3], vec![1, 2, 3]]);
+        let nested = FDisj(vec![FDisj(vec![FConj(vec![FVar(1), FVar(2)])]), FVar(3)]);
+        assert_eq!(cube_set(nested), vec![vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn groups_expand_to_one_cube_per_free_literal() {
+        let group = amo::AmoGroup {
+            lits: vec![1, -2, 3],
+            exactly_one: false,
+        };
+        assert_eq!(
+            cube_set(proc_group(&group)),
+            vec![vec![-1, -3], vec![-1, 2], vec![2, -3]]
+        );
+        let group = amo::AmoGroup {
+            exactly_one: true,
+            ..group
+        };
+        assert_eq!(
+            cube_set(proc_group(&group)),
+            vec![vec![-1, -2, -3], vec![-1, 2, 3], vec![1, 2, -3]]
+        );
+    }
+
+    #[test]
+    fn traced_cubes_record_each_pick() {
+        // (1 \/ 2) /\ -1 /\ (at most 1 of 2, 3)
+        let parts = vec![
+            proc_clause(&vec![1, 2]),
+            proc_clause(&vec![-1]),
+            FAtMost(1, vec![FVar(2), FVar(3)]),
+        ];
+        let dnfs: Vec<Formula> = parts.clone().into_iter().map(to_dnf).collect();
+        let traced = traced_cubes(dnfs.clone());
+        // The same cubes as the untraced cross
+        let mut lits: Vec<Cube> = dnf::normalize(traced.iter().map(|t| t.lits.clone()).collect());
+        lits.sort_unstable();
+        assert_eq!(lits, cube_set(FConj(parts)));
+        for cube in &traced {
+            assert_eq!(cube.chosen.len(), 3);
+            assert_eq!(cube.chosen[0], 1);
+            for (i, &j) in cube.chosen.iter().enumerate() {
+                assert!(disjunct_lits(&dnfs[i], j)
+                    .iter()
+                    .all(|l| cube.lits.contains(l)));
+            }
+        }
+        let forced = provenance::forced(&traced);
+        assert_eq!(forced[..2], [(0, 1), (1, 0)]);
+        assert_eq!(disjunct_lits(&dnfs[0], 1), vec![2]);
+    }
+
+    #[test]
+    fn negated_disjs_cross_their_negated_disjuncts() {
+        // -(1 \/ (2 /\ -3)) = -1 /\ (-2 \/ 3)
+        let f = FNeg(Box::new(FDisj(vec![
+            FVar(1),
+            FConj(vec![FVar(2), proc_lit(-3)]),
+        ])));
+        assert_eq!(cube_set(f), vec![vec![-1, -2], vec![-1, 3]]);
+    }
+
+    #[test]
+    fn tautologies_have_no_counterexample() {
+        // (1 /\ 2) \/ -1 \/ -2
+        let valid = FDisj(vec![
+            FConj(vec![FVar(1), FVar(2)]),
+            proc_lit(-1),
+            proc_lit(-2),
+        ]);
+        assert!(is_tautology(valid.clone()));
+        assert_eq!(formula_counterexample(valid), None);
+        let invalid = FDisj(vec![FConj(vec![FVar(1), FVar(2)]), proc_lit(-1)]);
+        let cex = formula_counterexample(invalid).unwrap();
+        let model = cnf::model_from_lits(&cex, 2);
+        assert_eq!(model, vec![false, true, false]);
+        // A clause is only valid if it has both literals of a variable
+        assert!(formula_counterexample(proc_clause(&vec![1, 2])).is_some());
+        assert_eq!(formula_counterexample(proc_clause(&vec![1, 2, -1])), None);
+        assert!(!is_tautology(proc_clause(&vec![1, 2])));
+        // Valid without any clause being valid: at least 1 of (1, -1)
+        assert!(is_tautology(FAtLeast(1, vec![FVar(1), proc_lit(-1)])));
+        for f in random_formulas(46, 200, 3) {
+            let valid = assignments(3).iter().all(|model| eval(&f, model));
+            assert_eq!(is_tautology(f.clone()), valid, "{f:?}");
+        }
+    }
+
+    // The truth value of f under the model
+    fn eval(f: &Formula, model: &cnf::Model) -> bool {
+        let count = |fvec: &[Formula]| fvec.iter().filter(|g| eval(g, model)).count() as u32;
+        match f {
+            FVar(x) => model[*x as usize],
+            FNeg(g) => !eval(g, model),
+            FDisj(fvec) => fvec.iter().any(|g| eval(g, model)),
+            FConj(fvec) => fvec.iter().all(|g| eval(g, model)),
+            FAtMost(k, fvec) => count(fvec) <= *k,
+            FAtLeast(k, fvec) => count(fvec) >= *k,
+            FExactly(k, fvec) => count(fvec) == *k,
+            FXor(fvec) => count(fvec) % 2 == 1,
+        }
+    }
+
+    fn random_formula(rng: &mut Lcg, depth: u32, num_vars: u32) -> Formula {
+        let choice = if depth == 0 { 0 } else { rng.below(9) };
+        let sub = |rng: &mut Lcg| -> Vec<Formula> {
+            (0..rng.below(4))
+                .map(|_| random_formula(rng, depth - 1, num_vars))
+                .collect()
+        };
+        match choice {
+            0 | 1 => proc_lit(1 + rng.below(num_vars as u64) as Lit),
+            2 => FNeg(Box::new(random_formula(rng, depth - 1, num_vars))),
+            3 => FDisj(sub(rng)),
+            4 => FConj(sub(rng)),
+            5 => FAtMost(rng.below(3) as u32, sub(rng)),
+            6 => FAtLeast(rng.below(3) as u32, sub(rng)),
+            7 => FExactly(rng.below(3) as u32, sub(rng)),
+            _ => FXor(sub(rng)),
+        }
+    }
+
+    // Small random formulas over 1..=num_vars, the same for a given seed
+    fn random_formulas(seed: u64, count: usize, num_vars: u32) -> Vec<Formula> {
+        let mut rng = Lcg(seed);
+        (0..count)
+            .map(|_| random_formula(&mut rng, 3, num_vars))
+            .collect()
+    }
+
+    fn assignments(num_vars: usize) -> Vec<cnf::Model> {
+        (0..1u32 << num_vars)
+            .map(|bits| {
+                (0..=num_vars)
+                    .map(|v| v > 0 && bits >> (v - 1) & 1 == 1)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn distributive_cnf_is_equivalent() {
+        for f in random_formulas(50, 300, 4) {
+            let cnf = clauses(to_cnf(f.clone()));
+            for model in assignments(4) {
+                let satisfied = cnf
+                    .iter()
+                    .all(|clause| clause.iter().any(|&l| cnf::lit_true(&model, l)));
+                assert_eq!(satisfied, eval(&f, &model), "{f:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn tseitin_and_pg_project_to_the_models() {
+        for f in random_formulas(51, 300, 4) {
+            for polarity in [false, true] {
+                let (cnf, _) = tseitin(&f, 4, polarity);
+                for model in assignments(4) {
+                    // The encoding with the original variables fixed
+                    let mut fixed = cnf.clone();
+                    fixed.extend(
+                        (1..=4).map(|v| vec![if model[v] { v as Lit } else { -(v as Lit) }]),
+                    );
+                    let extends = proof::refute(&fixed, 0).is_ok();
+                    assert_eq!(extends, eval(&f, &model), "{f:?} (polarity {polarity})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tseitin_grows_linearly_on_cards_and_xors() {
+        let fvec: Vec<Formula> = (1..=40).map(FVar).collect();
+        let (cnf, vars) = tseitin(&FXor(fvec.clone()), 40, false);
+        assert!(
+            cnf.len() <= 4 * 40 + 2 && vars <= 2 * 40 + 2,
+            "{}",
+            cnf.len()
+        );
+        // choose(40, 6) subsets would be millions of cubes
+        let (cnf, _) = tseitin(&FAtMost(5, fvec), 40, true);
+        assert!(cnf.len() <= 6 * 6 * 40 + 3, "{}", cnf.len());
+    }
+    #[test]
+    fn entailment_matches_the_models() {
+        let formulas = random_formulas(47, 40, 3);
+        let models = assignments(3);
+        for f in &formulas {
+            for g in &formulas[..10] {
+                let holds = models.iter().all(|m| !eval(f, m) || eval(g, m));
+                match entails(f.clone(), g.clone()) {
+                    Ok(()) => assert!(holds, "{f:?} |= {g:?}"),
+                    Err(cex) => {
+                        let model = cnf::model_from_lits(&cex, 3);
+                        assert!(eval(f, &model) && !eval(g, &model), "{f:?} |= {g:?}");
+                    }
+                }
+                let same = models.iter().all(|m| eval(f, m) == eval(g, m));
+                assert_eq!(equivalent(f.clone(), g.clone()).is_ok(), same);
+            }
+        }
+        // De Morgan
+        let f = FNeg(Box::new(FConj(vec![FVar(1), FVar(2)])));
+        assert_eq!(equivalent(f, proc_clause(&vec![-1, -2])), Ok(()));
+    }
 }
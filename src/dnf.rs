@@ -0,0 +1,825 @@
+// Queries over the cube set of a DNF, as produced by formula_cross/to_dnf
+// (every FConj of the final FDisj) or by val_set_cross (every Valuation),
+// or as read from a "p dnf" file.
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use rayon::join;
+
+use crate::bignum::BigUint;
+use crate::cnf::{model_from_lits, parse_clause, parse_lines, var, Lit, Model};
+
+// A conjunction of literals; a model satisfies the DNF iff it extends a cube
+pub type Cube = Vec<Lit>;
+
+// Branches with fewer cubes than this are counted on the current thread
+const PAR_CUTOFF: usize = 256;
+
+/**
+ * Spec: sorts and dedups every cube, dropping the inconsistent ones
+ * (containing both x and -x), which have no models
+ */
+pub fn normalize(cubes: Vec<Cube>) -> Vec<Cube> {
+    cubes
+        .into_iter()
+        .filter_map(|mut cube| {
+            cube.sort_unstable_by_key(|&l| (var(l), l));
+            cube.dedup();
+            if cube.windows(2).any(|w| w[0] == -w[1]) {
+                None
+            } else {
+                Some(cube)
+            }
+        })
+        .collect()
+}
+
+/**
+ * Spec: the number of assignments to variables 1..=num_vars that extend at
+ * least one cube. Cubes overlap, so this splits on the most frequent variable
+ * (F = x F|x + -x F|-x) until every branch is empty or contains the empty cube.
+ */
+pub fn count_models(cubes: &[Cube], num_vars: usize) -> BigUint {
+    count_rec(normalize(cubes.to_vec()), num_vars)
+}
+
+// Invariant: cubes are normalized and only mention scope variables
+fn count_rec(cubes: Vec<Cube>, scope: usize) -> BigUint {
+    if cubes.is_empty() {
+        return BigUint::zero();
+    }
+    if cubes.iter().any(|cube| cube.is_empty()) {
+        return BigUint::pow2(scope);
+    }
+    let (x, mentioned) = split_var(&cubes);
+    // Variables in scope but in no cube are free in every model
+    let free = scope - mentioned;
+    let (pos, neg) = split(cubes, x);
+    let sub_scope = mentioned - 1;
+    let (c_pos, c_neg) = if pos.len() + neg.len() > PAR_CUTOFF {
+        join(|| count_rec(pos, sub_scope), || count_rec(neg, sub_scope))
+    } else {
+        (count_rec(pos, sub_scope), count_rec(neg, sub_scope))
+    };
+    (&c_pos + &c_neg).mul_pow2(free)
+}
+
+// The most frequent variable of non-empty cubes, and how many variables they mention
+fn split_var(cubes: &[Cube]) -> (usize, usize) {
+    let mut freq: std::collections::HashMap<usize, usize> = std::collections::HashMap::new();
+    for cube in cubes {
+        for &l in cube {
+            *freq.entry(var(l)).or_insert(0) += 1;
+        }
+    }
+    let (&x, _) = freq
+        .iter()
+        .max_by_key(|(&v, &n)| (n, std::cmp::Reverse(v)))
+        .expect("Non-empty cubes mention some variable");
+    (x, freq.len())
+}
+
+// The cubes of F|x and F|-x
+fn split(cubes: Vec<Cube>, x: usize) -> (Vec<Cube>, Vec<Cube>) {
+    let mut pos = Vec::new();
+    let mut neg = Vec::new();
+    for cube in cubes {
+        match cube.iter().position(|&l| var(l) == x) {
+            Some(i) => {
+                let l = cube[i];
+                let mut rest = cube;
+                rest.remove(i);
+                if l > 0 {
+                    pos.push(rest);
+                } else {
+                    neg.push(rest);
+                }
+            }
+            None => {
+                pos.push(cube.clone());
+                neg.push(cube);
+            }
+        }
+    }
+    (pos, neg)
+}
+
+/**
+ * Spec: pairwise disjoint cubes with the same models, by the same splitting
+ * as count_models (one cube per path to a branch containing the empty cube).
+ * This can blow up when the cubes overlap a lot.
+ */
+pub fn disjoint(cubes: &[Cube]) -> Vec<Cube> {
+    disjoint_rec(normalize(cubes.to_vec()))
+}
+
+fn disjoint_rec(cubes: Vec<Cube>) -> Vec<Cube> {
+    if cubes.is_empty() {
+        return Vec::new();
+    }
+    if cubes.iter().any(|cube| cube.is_empty()) {
+        return vec![Vec::new()];
+    }
+    let (x, _) = split_var(&cubes);
+    let (pos, neg) = split(cubes, x);
+    let (d_pos, d_neg) = if pos.len() + neg.len() > PAR_CUTOFF {
+        join(|| disjoint_rec(pos), || disjoint_rec(neg))
+    } else {
+        (disjoint_rec(pos), disjoint_rec(neg))
+    };
+    let x = x as Lit;
+    let with = |l: Lit, cube: Cube| {
+        let mut ret = vec![l];
+        ret.extend(cube);
+        ret
+    };
+    let mut ret_vec: Vec<Cube> = d_pos.into_iter().map(|cube| with(x, cube)).collect();
+    ret_vec.extend(d_neg.into_iter().map(|cube| with(-x, cube)));
+    ret_vec
+}
+
+/**
+ * Spec: a partial assignment no cube is consistent with (so every completion
+ * of it falsifies the DNF), or None if the DNF is a tautology. Splits like
+ * count_models, stopping at the first branch left without cubes.
+ */
+pub fn counterexample(cubes: &[Cube]) -> Option<Vec<Lit>> {
+    counterexample_rec(normalize(cubes.to_vec()))
+}
+
+// Spec: whether every assignment extends some cube
+pub fn is_tautology(cubes: &[Cube]) -> bool {
+    counterexample(cubes).is_none()
+}
+
+fn counterexample_rec(cubes: Vec<Cube>) -> Option<Vec<Lit>> {
+    if cubes.is_empty() {
+        return Some(Vec::new());
+    }
+    if cubes.iter().any(|cube| cube.is_empty()) {
+        return None;
+    }
+    let (x, _) = split_var(&cubes);
+    let x_lit = x as Lit;
+    // If x only occurs positively, F|-x has a subset of F|x's cubes, so F|-x
+    // failing is the only way for F to fail on x (and the other way around)
+    let has_pos = cubes.iter().any(|cube| cube.contains(&x_lit));
+    let has_neg = cubes.iter().any(|cube| cube.contains(&-x_lit));
+    let (pos, neg) = split(cubes, x);
+    let with = |l: Lit, cex: Vec<Lit>| {
+        let mut ret = vec![l];
+        ret.extend(cex);
+        ret
+    };
+    match (has_pos, has_neg) {
+        (true, false) => counterexample_rec(neg).map(|cex| with(-x_lit, cex)),
+        (false, true) => counterexample_rec(pos).map(|cex| with(x_lit, cex)),
+        _ if pos.len() + neg.len() > PAR_CUTOFF => {
+            match join(|| counterexample_rec(pos), || counterexample_rec(neg)) {
+                (Some(cex), _) => Some(with(x_lit, cex)),
+                (None, cex) => cex.map(|cex| with(-x_lit, cex)),
+            }
+        }
+        _ => counterexample_rec(pos)
+            .map(|cex| with(x_lit, cex))
+            .or_else(|| counterexample_rec(neg).map(|cex| with(-x_lit, cex))),
+    }
+}
+
+// Sorted (by variable) cube a is a subset of sorted cube b
+fn is_subset(a: &[Lit], b: &[Lit]) -> bool {
+    let mut rest = b.iter();
+    a.iter().all(|&l| rest.any(|&l2| l2 == l))
+}
+
+/**
+ * Spec: normalizes the cubes and drops every cube that has another one as a
+ * subset (its models are already covered), as well as duplicates
+ */
+pub fn remove_subsumed(cubes: Vec<Cube>) -> Vec<Cube> {
+    let mut cubes = normalize(cubes);
+    cubes.sort_unstable_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    cubes.dedup();
+    // Only a shorter cube can be a proper subset, and those come first
+    let kept: Vec<bool> = cubes
+        .par_iter()
+        .enumerate()
+        .map(|(i, cube)| {
+            !cubes[..i]
+                .iter()
+                .take_while(|other| other.len() < cube.len())
+                .any(|other| is_subset(other, cube))
+        })
+        .collect();
+    cubes
+        .into_iter()
+        .zip(kept)
+        .filter_map(|(cube, keep)| keep.then_some(cube))
+        .collect()
+}
+
+/**
+ * Spec: the cubes of F conditioned on the assignment (a consistent set of
+ * literals): cubes contradicting it are dropped and its literals removed
+ * from the rest, so the assigned variables no longer occur
+ */
+pub fn condition(cubes: &[Cube], assignment: &[Lit]) -> Vec<Cube> {
+    let num_vars = assignment.iter().map(|&l| var(l)).max().unwrap_or(0);
+    let mut value = vec![None; num_vars + 1];
+    for &l in assignment {
+        value[var(l)] = Some(l > 0);
+    }
+    let value_of = |l: Lit| {
+        value
+            .get(var(l))
+            .copied()
+            .flatten()
+            .map(|val| val == (l > 0))
+    };
+    normalize(cubes.to_vec())
+        .into_par_iter()
+        .filter(|cube| cube.iter().all(|&l| value_of(l) != Some(false)))
+        .map(|cube| {
+            cube.into_iter()
+                .filter(|&l| value_of(l).is_none())
+                .collect()
+        })
+        .collect()
+}
+
+/**
+ * Spec: the cubes of F with the variables existentially quantified away:
+ * their literals are deleted from every cube, then subsumed cubes removed
+ */
+pub fn forget(cubes: &[Cube], vars: &[usize]) -> Vec<Cube> {
+    let gone: HashSet<usize> = vars.iter().copied().collect();
+    // An inconsistent cube must not become consistent by losing the variable
+    remove_subsumed(
+        normalize(cubes.to_vec())
+            .into_iter()
+            .map(|cube| {
+                cube.iter()
+                    .copied()
+                    .filter(|&l| !gone.contains(&var(l)))
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/**
+ * Spec: the backbone of the cube set: the literals true in every model, i.e.
+ * the literals shared by all consistent cubes (sorted by variable).
+ * None if there are no consistent cubes (UNSAT).
+ */
+pub fn backbone(cubes: &[Cube]) -> Option<Vec<Lit>> {
+    normalize(cubes.to_vec())
+        .into_par_iter()
+        .reduce_with(|a, b| {
+            // Both sorted by variable, so intersect by merging
+            let mut common = Vec::new();
+            let (mut i, mut j) = (0, 0);
+            while i < a.len() && j < b.len() {
+                let (ka, kb) = ((var(a[i]), a[i]), (var(b[j]), b[j]));
+                if ka == kb {
+                    common.push(a[i]);
+                }
+                if ka <= kb {
+                    i += 1;
+                }
+                if kb <= ka {
+                    j += 1;
+                }
+            }
+            common
+        })
+}
+
+/**
+ * Literal costs: a model pays cost(l) for every literal l it makes true.
+ * Index v holds the costs of v and -v, variables past the end cost nothing.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Costs {
+    pos: Vec<i64>,
+    neg: Vec<i64>,
+}
+
+impl Costs {
+    pub fn add(&mut self, l: Lit, cost: i64) {
+        if var(l) >= self.pos.len() {
+            self.pos.resize(var(l) + 1, 0);
+            self.neg.resize(var(l) + 1, 0);
+        }
+        if l > 0 {
+            self.pos[var(l)] += cost;
+        } else {
+            self.neg[var(l)] += cost;
+        }
+    }
+
+    pub fn cost(&self, l: Lit) -> i64 {
+        let costs = if l > 0 { &self.pos } else { &self.neg };
+        costs.get(var(l)).copied().unwrap_or(0)
+    }
+
+    // The cheaper literal of v, -v on ties
+    fn cheapest(&self, v: usize) -> Lit {
+        let v = v as Lit;
+        if self.cost(v) < self.cost(-v) {
+            v
+        } else {
+            -v
+        }
+    }
+
+    // What l costs beyond the cheaper polarity of its variable
+    pub fn excess(&self, l: Lit) -> i64 {
+        self.cost(l) - self.cost(self.cheapest(var(l)))
+    }
+
+    // The cost of setting every variable to its cheaper polarity
+    pub fn base(&self) -> i64 {
+        (1..self.pos.len())
+            .map(|v| self.cost(self.cheapest(v)))
+            .sum()
+    }
+
+    /**
+     * Spec: the model fixing lits and setting every other variable to its
+     * cheaper polarity (it grows past num_vars to cover the costed variables)
+     */
+    pub fn complete(&self, lits: &[Lit], num_vars: usize) -> Model {
+        let mut all: Vec<Lit> = (1..self.pos.len()).map(|v| self.cheapest(v)).collect();
+        all.extend_from_slice(lits);
+        model_from_lits(&all, num_vars)
+    }
+}
+
+/**
+ * Spec: the cube whose cheapest completion costs least, with that cost minus
+ * costs.base() (so the excesses of variable-disjoint cube sets add up).
+ * Cubes must be consistent. None if there are no cubes.
+ */
+pub fn best_cube<'a>(cubes: &'a [Cube], costs: &Costs) -> Option<(i64, &'a Cube)> {
+    cubes
+        .par_iter()
+        .map(|cube| {
+            // A repeated literal is paid for once
+            let mut lits = cube.clone();
+            lits.sort_unstable();
+            lits.dedup();
+            (lits.iter().map(|&l| costs.excess(l)).sum(), cube)
+        })
+        .min_by_key(|(excess, _)| *excess)
+}
+
+/**
+ * Spec: a minimum cost model over variables 1..=num_vars extending some cube,
+ * with its cost. Fixed literals pay their cost, free variables take their
+ * cheaper polarity. None if there are no cubes.
+ */
+pub fn optimize(cubes: &[Cube], costs: &Costs, num_vars: usize) -> Option<(i64, Model)> {
+    let (excess, cube) = best_cube(cubes, costs)?;
+    Some((costs.base() + excess, costs.complete(cube, num_vars)))
+}
+
+/**
+ * Spec: parses a costs file, one "<lit> <cost>" pair per line ("c" comment
+ * and blank lines are skipped). Costs of repeated literals add up.
+ */
+pub fn parse_costs<R: BufRead>(reader: R) -> Costs {
+    let mut costs = Costs::default();
+    parse_lines(reader, "COST", |fields| match fields {
+        [l, cost] => {
+            let l: Lit = l.parse().ok().filter(|&l| l != 0)?;
+            costs.add(l, cost.parse().ok()?);
+            Some(())
+        }
+        _ => None,
+    });
+    costs
+}
+
+/**
+ * Spec: parses a DNF file in the DIMACS style: a "p dnf <vars> <cubes>"
+ * header and one cube per line ("1 -2 0": 1 and -2), comment and blank lines
+ * skipped. Returns num_vars (raised to the largest variable) and the cubes.
+ */
+pub fn parse_dnf<R: BufRead>(reader: R) -> (usize, Vec<Cube>) {
+    let mut num_vars = 0;
+    let mut cubes = Vec::new();
+    for line in reader.lines() {
+        let line = line.expect("String needed");
+        let good_line = line.trim();
+        if good_line.is_empty() || good_line.starts_with('c') {
+            continue;
+        }
+        if good_line.starts_with('p') {
+            let header: Vec<&str> = good_line.split_whitespace().collect();
+            num_vars = match header[..] {
+                [_, "dnf", n, ..] => n.parse().ok(),
+                _ => None,
+            }
+            .unwrap_or_else(|| panic!("CRITICAL ERROR PARSING HEADER: '{good_line}'"));
+            continue;
+        }
+        let cube = parse_clause(good_line);
+        for &l in &cube {
+            num_vars = num_vars.max(var(l));
+        }
+        cubes.push(cube);
+    }
+    (num_vars, cubes)
+}
+
+/**
+ * Spec: writes the cubes as a DNF file ("p dnf" header, one cube per line),
+ * readable by parse_dnf
+ */
+pub fn write_dnf<W: Write>(cubes: &[Cube], num_vars: usize, mut w: W) -> std::io::Result<()> {
+    writeln!(w, "p dnf {} {}", num_vars, cubes.len())?;
+    for cube in cubes {
+        for l in cube {
+            write!(w, "{l} ")?;
+        }
+        writeln!(w, "0")?;
+    }
+    w.flush()
+}
+
+/**
+ * A compiled DNF: normalized cubes, none subsuming another. The boolean
+ * operations return new compiled DNFs: & crosses the cubes (dropping the
+ * inconsistent products), | unites them, ! dualizes (crossing the negated
+ * cubes as clauses one at a time) and ^ is (a & !b) | (!a & b).
+ * Subsumed cubes are removed after every operation, which keeps the
+ * intermediate results of ! small.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dnf {
+    cubes: Vec<Cube>,
+}
+
+impl Dnf {
+    pub fn new(cubes: Vec<Cube>) -> Dnf {
+        Dnf {
+            cubes: remove_subsumed(cubes),
+        }
+    }
+
+    // The constant true (the empty cube) / false (no cubes)
+    pub fn top() -> Dnf {
+        Dnf {
+            cubes: vec![Vec::new()],
+        }
+    }
+
+    pub fn bottom() -> Dnf {
+        Dnf::default()
+    }
+
+    pub fn cubes(&self) -> &[Cube] {
+        &self.cubes
+    }
+
+    pub fn into_cubes(self) -> Vec<Cube> {
+        self.cubes
+    }
+
+    pub fn is_top(&self) -> bool {
+        self.cubes.iter().any(|cube| cube.is_empty())
+    }
+
+    pub fn is_bottom(&self) -> bool {
+        self.cubes.is_empty()
+    }
+}
+
+// The union of two cubes sorted by variable, None if they conflict
+fn merge_cubes(a: &[Lit], b: &[Lit]) -> Option<Cube> {
+    let mut ret = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let (la, lb) = (a[i], b[j]);
+        if var(la) == var(lb) && la != lb {
+            return None;
+        }
+        if (var(la), la) <= (var(lb), lb) {
+            ret.push(la);
+            i += 1;
+            if la == lb {
+                j += 1;
+            }
+        } else {
+            ret.push(lb);
+            j += 1;
+        }
+    }
+    ret.extend_from_slice(&a[i..]);
+    ret.extend_from_slice(&b[j..]);
+    Some(ret)
+}
+
+impl BitAnd for &Dnf {
+    type Output = Dnf;
+
+    fn bitand(self, other: &Dnf) -> Dnf {
+        let cubes = self
+            .cubes
+            .par_iter()
+            .flat_map_iter(|a| other.cubes.iter().filter_map(move |b| merge_cubes(a, b)))
+            .collect();
+        Dnf::new(cubes)
+    }
+}
+
+impl BitOr for &Dnf {
+    type Output = Dnf;
+
+    fn bitor(self, other: &Dnf) -> Dnf {
+        Dnf::new([self.cubes.clone(), other.cubes.clone()].concat())
+    }
+}
+
+impl Not for &Dnf {
+    type Output = Dnf;
+
+    fn not(self) -> Dnf {
+        // -(C1 | ... | Cn) = -C1 & ... & -Cn, each -Ci a clause of unit cubes
+        self.cubes.iter().fold(Dnf::top(), |acc, cube| {
+            let clause = Dnf::new(cube.iter().map(|&l| vec![-l]).collect());
+            &acc & &clause
+        })
+    }
+}
+
+impl BitXor for &Dnf {
+    type Output = Dnf;
+
+    fn bitxor(self, other: &Dnf) -> Dnf {
+        &(self & &!other) | &(&!self & other)
+    }
+}
+
+impl BitAnd for Dnf {
+    type Output = Dnf;
+
+    fn bitand(self, other: Dnf) -> Dnf {
+        &self & &other
+    }
+}
+
+impl BitOr for Dnf {
+    type Output = Dnf;
+
+    fn bitor(self, other: Dnf) -> Dnf {
+        &self | &other
+    }
+}
+
+impl Not for Dnf {
+    type Output = Dnf;
+
+    fn not(self) -> Dnf {
+        !&self
+    }
+}
+
+impl BitXor for Dnf {
+    type Output = Dnf;
+
+    fn bitxor(self, other: Dnf) -> Dnf {
+        &self ^ &other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cnf::lit_true;
+    use crate::preprocess::testing::Lcg;
+
+    // The assignments to 1..=num_vars extending some cube, by brute force
+    fn models(cubes: &[Cube], num_vars: usize) -> Vec<Model> {
+        (0..1u32 << num_vars)
+            .map(|bits| {
+                (0..=num_vars)
+                    .map(|v| v > 0 && bits >> (v - 1) & 1 == 1)
+                    .collect::<Model>()
+            })
+            .filter(|model| {
+                cubes
+                    .iter()
+                    .any(|cube| cube.iter().all(|&l| lit_true(model, l)))
+            })
+            .collect()
+    }
+
+    // Small random cubes, the same for a given seed
+    fn random_dnfs(seed: u64, count: usize, num_vars: usize) -> Vec<Vec<Cube>> {
+        let mut rng = Lcg(seed);
+        (0..count)
+            .map(|_| {
+                (0..rng.below(6))
+                    .map(|_| {
+                        let len = rng.below(4);
+                        rng.lits(len, num_vars)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn normalize_drops_inconsistent_cubes() {
+        assert_eq!(
+            normalize(vec![vec![2, -1, 2], vec![1, 3, -1], vec![]]),
+            vec![vec![-1, 2], vec![]]
+        );
+    }
+
+    #[test]
+    fn counts_overlapping_cubes() {
+        assert_eq!(count_models(&[], 3), BigUint::zero());
+        assert_eq!(count_models(&[vec![]], 3), BigUint::from(8));
+        // 1 or 2 over 3 variables: 6 models
+        assert_eq!(count_models(&[vec![1], vec![2]], 3), BigUint::from(6));
+        assert_eq!(count_models(&[vec![1]], 200), BigUint::pow2(199));
+    }
+
+    #[test]
+    fn parses_costs() {
+        let costs = parse_costs("c costs\n1 3\n\n-2 -1\n1 2\n".as_bytes());
+        assert_eq!(costs.cost(1), 5);
+        assert_eq!(costs.cost(-2), -1);
+        assert_eq!(costs.cost(3), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "PARSING COST LINE")]
+    fn rejects_bad_cost_lines() {
+        parse_costs("0 4\n".as_bytes());
+    }
+
+    #[test]
+    fn optimum_matches_brute_force() {
+        let costs = parse_costs("1 3\n-1 1\n2 -2\n-3 4\n4 1\n-4 1\n".as_bytes());
+        let model_cost = |model: &Model| -> i64 {
+            (1..=5)
+                .map(|v| costs.cost(if model[v] { v as Lit } else { -(v as Lit) }))
+                .sum()
+        };
+        // The engine's cubes are consistent
+        for cubes in random_dnfs(39, 200, 5).into_iter().map(normalize) {
+            let best = optimize(&cubes, &costs, 5);
+            let brute = models(&cubes, 5).iter().map(model_cost).min();
+            assert_eq!(best.as_ref().map(|(cost, _)| *cost), brute, "{cubes:?}");
+            if let Some((cost, model)) = best {
+                assert_eq!(model_cost(&model), cost);
+                assert!(models(&cubes, 5).contains(&model));
+            }
+        }
+    }
+
+    #[test]
+    fn backbone_matches_brute_force() {
+        assert_eq!(backbone(&[vec![1, -1]]), None);
+        for cubes in random_dnfs(41, 300, 5) {
+            let all = models(&cubes, 5);
+            let expected = (!all.is_empty()).then(|| {
+                (1..=5)
+                    .flat_map(|v| [v as Lit, -(v as Lit)])
+                    .filter(|&l| all.iter().all(|model| lit_true(model, l)))
+                    .collect::<Vec<Lit>>()
+            });
+            assert_eq!(backbone(&cubes), expected, "{cubes:?}");
+        }
+    }
+
+    #[test]
+    fn counts_match_brute_force() {
+        for cubes in random_dnfs(33, 300, 5) {
+            assert_eq!(
+                count_models(&cubes, 5),
+                BigUint::from(models(&cubes, 5).len() as u64),
+                "{cubes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn counterexamples_match_brute_force() {
+        assert_eq!(counterexample(&[vec![1, 2], vec![-1], vec![-2]]), None);
+        assert_eq!(counterexample(&[vec![]]), None);
+        assert_eq!(counterexample(&[]), Some(vec![]));
+        assert!(is_tautology(&[vec![1, 2], vec![-1], vec![-2]]));
+        assert!(!is_tautology(&[vec![1, 2], vec![-1]]) && !is_tautology(&[]));
+        let mut tautologies = 0;
+        for num_vars in [2, 3] {
+            for cubes in random_dnfs(46, 300, num_vars) {
+                let all = models(&cubes, num_vars);
+                let cex = counterexample(&cubes);
+                assert_eq!(is_tautology(&cubes), cex.is_none());
+                match cex {
+                    None => {
+                        assert_eq!(all.len(), 1 << num_vars, "{cubes:?}");
+                        tautologies += 1;
+                    }
+                    // No model of the cubes extends it
+                    Some(cex) => assert!(
+                        !all.iter()
+                            .any(|model| cex.iter().all(|&l| lit_true(model, l))),
+                        "{cubes:?}: {cex:?}"
+                    ),
+                }
+            }
+        }
+        assert!(tautologies > 0);
+    }
+
+    #[test]
+    fn conditioning_and_forgetting_match_brute_force() {
+        let extends = |cubes: &[Cube], model: &Model| {
+            cubes
+                .iter()
+                .any(|cube| cube.iter().all(|&l| lit_true(model, l)))
+        };
+        for cubes in random_dnfs(48, 300, 4) {
+            let conditioned = condition(&cubes, &[1, -3]);
+            let forgotten = forget(&cubes, &[2, 4]);
+            for model in models(&[vec![]], 4) {
+                // F|1,-3 holds where F holds with 1 true and 3 false
+                let mut fixed = model.clone();
+                fixed[1] = true;
+                fixed[3] = false;
+                assert_eq!(
+                    extends(&conditioned, &model),
+                    extends(&cubes, &fixed),
+                    "{cubes:?}"
+                );
+                assert!(conditioned
+                    .iter()
+                    .flatten()
+                    .all(|&l| var(l) != 1 && var(l) != 3));
+                // Some values of 2 and 4 make F hold
+                let witness = models(&[vec![]], 4).into_iter().any(|other| {
+                    let mut mixed = model.clone();
+                    mixed[2] = other[2];
+                    mixed[4] = other[4];
+                    extends(&cubes, &mixed)
+                });
+                assert_eq!(extends(&forgotten, &model), witness, "{cubes:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn operations_match_brute_force() {
+        let dnfs: Vec<Dnf> = random_dnfs(49, 40, 4).into_iter().map(Dnf::new).collect();
+        let all = models(&[vec![]], 4);
+        let holds = |f: &Dnf, model: &Model| models(f.cubes(), 4).contains(model);
+        for pair in dnfs.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let (and, or, not, xor) = (a & b, a | b, !a, a ^ b);
+            for model in &all {
+                let (x, y) = (holds(a, model), holds(b, model));
+                assert_eq!(holds(&and, model), x && y, "{a:?} & {b:?}");
+                assert_eq!(holds(&or, model), x || y, "{a:?} | {b:?}");
+                assert_eq!(holds(&not, model), !x, "!{a:?}");
+                assert_eq!(holds(&xor, model), x != y, "{a:?} ^ {b:?}");
+            }
+            // The results stay compiled: no cube subsumes another
+            for cubes in [and.cubes(), or.cubes(), not.cubes(), xor.cubes()] {
+                assert_eq!(remove_subsumed(cubes.to_vec()).len(), cubes.len());
+            }
+        }
+        assert!((!Dnf::bottom()).is_top() && (!Dnf::top()).is_bottom());
+        assert!((Dnf::new(vec![vec![1]]) & Dnf::new(vec![vec![-1]])).is_bottom());
+    }
+
+    #[test]
+    fn writes_what_it_parses() {
+        let text = "p dnf 5 3\n1 -2 0\n0\n-5 0\n";
+        let (num_vars, cubes) = parse_dnf(format!("c comment\n{text}").as_bytes());
+        assert_eq!(
+            (num_vars, cubes.clone()),
+            (5, vec![vec![1, -2], vec![], vec![-5]])
+        );
+        let mut out = Vec::new();
+        write_dnf(&cubes, num_vars, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), text);
+        // Cubes may mention variables past the header's count
+        assert_eq!(parse_dnf("p dnf 1 1\n-3 0\n".as_bytes()).0, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "PARSING HEADER")]
+    fn only_reads_dnf_headers() {
+        parse_dnf("p cnf 2 1\n1 0\n".as_bytes());
+    }
+}
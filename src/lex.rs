@@ -0,0 +1,246 @@
+// Lexicographically smallest / largest models under a variable order.
+//
+// Models are compared as bit strings in the given order (false < true). Over
+// a cube set the best model is the best cube's completion with every free
+// variable at the preferred value; the lazy search instead assigns the
+// variables in order over the DNFs of the conjuncts, so it stops at the first
+// full assignment without ever building their cross product.
+use std::io::BufRead;
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::cnf::{parse_lines, var, Lit, Model};
+use crate::dnf::Cube;
+
+/**
+ * Spec: parses a variable order file: variables separated by whitespace,
+ * "c" comment lines skipped
+ */
+pub fn parse_order<R: BufRead>(reader: R) -> Vec<usize> {
+    let mut order = Vec::new();
+    parse_lines(reader, "ORDER", |fields| {
+        for field in fields {
+            order.push(field.parse().ok().filter(|&v| v > 0)?);
+        }
+        Some(())
+    });
+    order
+}
+
+/**
+ * Spec: the listed variables (first occurrence), then every other variable
+ * of 1..=num_vars in increasing order
+ */
+pub fn full_order(order: &[usize], num_vars: usize) -> Vec<usize> {
+    let num_vars = num_vars.max(order.iter().copied().max().unwrap_or(0));
+    let mut listed = vec![false; num_vars + 1];
+    let mut ret_vec = Vec::with_capacity(num_vars);
+    for v in order.iter().copied().chain(1..=num_vars) {
+        if !listed[v] {
+            listed[v] = true;
+            ret_vec.push(v);
+        }
+    }
+    ret_vec
+}
+
+/**
+ * Spec: the cube with the lexicographically smallest (largest if max)
+ * completion. The completion of a cube only departs from the preferred
+ * all-false (all-true) model at its other-polarity literals, so the best cube
+ * is the one whose first such literal comes last in the order (ties broken by
+ * the next one, and having none left is best). None if there are no cubes.
+ */
+pub fn best_cube<'a>(cubes: &'a [Cube], order: &[usize], max: bool) -> Option<&'a Cube> {
+    let num_vars = order.iter().copied().max().unwrap_or(0);
+    let mut pos = vec![usize::MAX; num_vars + 1];
+    for (i, &v) in order.iter().enumerate() {
+        pos[v] = i;
+    }
+    cubes
+        .par_iter()
+        .map(|cube| {
+            let mut key: Vec<usize> = cube
+                .iter()
+                .filter(|&&l| (l > 0) != max)
+                .map(|&l| pos.get(var(l)).copied().unwrap_or(usize::MAX))
+                .collect();
+            key.sort_unstable();
+            key.dedup();
+            key.push(usize::MAX);
+            (key, cube)
+        })
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, cube)| cube)
+}
+
+/**
+ * Spec: the model fixing lits and setting every other variable of
+ * 1..=num_vars to the preferred value (true if max)
+ */
+pub fn complete(lits: &[Lit], num_vars: usize, max: bool) -> Model {
+    let mut model = vec![max; num_vars + 1];
+    model[0] = false;
+    for &l in lits {
+        if var(l) > num_vars {
+            model.resize(var(l) + 1, max);
+        }
+        model[var(l)] = l > 0;
+    }
+    model
+}
+
+// Depth first search in variable order over the conjuncts' cube sets
+struct LexSearch<'a> {
+    parts: &'a [Vec<Cube>],
+    // occurs[v] = the parts mentioning variable v
+    occurs: Vec<Vec<usize>>,
+    value: Vec<Option<bool>>,
+    max: bool,
+}
+
+impl LexSearch<'_> {
+    // Some cube of the part is consistent with the partial assignment
+    fn alive(&self, part: usize) -> bool {
+        self.parts[part]
+            .iter()
+            .any(|cube| cube.iter().all(|&l| self.value[var(l)] != Some(l < 0)))
+    }
+
+    /**
+     * Assigns the variables in order, preferred value first, backtracking
+     * over an explicit stack: there is one level per variable, too many for
+     * recursion on big instances
+     */
+    fn search(&mut self, order: &[usize]) -> bool {
+        // tried[d] = how many values of order[d] were tried, the last one is set
+        let mut tried: Vec<usize> = vec![0];
+        while let Some(&n) = tried.last() {
+            let d = tried.len() - 1;
+            if d == order.len() {
+                return true;
+            }
+            let v = order[d];
+            if n == 2 {
+                self.value[v] = None;
+                tried.pop();
+                continue;
+            }
+            tried[d] += 1;
+            self.value[v] = Some(if n == 0 { self.max } else { !self.max });
+            if self.occurs[v].iter().all(|&p| self.alive(p)) {
+                tried.push(0);
+            }
+        }
+        false
+    }
+}
+
+/**
+ * Spec: the lexicographically smallest (largest if max) model of the
+ * conjunction of parts, each given as its DNF cubes, without crossing them.
+ * order must be a full order (see full_order). None if UNSAT.
+ */
+pub fn lazy_search(parts: &[Vec<Cube>], order: &[usize], max: bool) -> Option<Model> {
+    let num_vars = order.iter().copied().max().unwrap_or(0);
+    let mut occurs = vec![Vec::new(); num_vars + 1];
+    for (p, part) in parts.iter().enumerate() {
+        let mut vars: Vec<usize> = part.iter().flatten().map(|&l| var(l)).collect();
+        vars.sort_unstable();
+        vars.dedup();
+        for v in vars {
+            occurs[v].push(p);
+        }
+    }
+    let mut search = LexSearch {
+        parts,
+        occurs,
+        value: vec![None; num_vars + 1],
+        max,
+    };
+    if !(0..parts.len()).all(|p| search.alive(p)) || !search.search(order) {
+        return None;
+    }
+    let mut model: Model = search.value.iter().map(|val| val == &Some(true)).collect();
+    model[0] = false;
+    Some(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The models of every part, by brute force, in increasing bit string order
+    fn models(parts: &[Vec<Cube>], order: &[usize]) -> Vec<Model> {
+        let n = order.len();
+        (0..1u32 << n)
+            .map(|bits| {
+                let mut model = vec![false; n + 1];
+                // The first variable of the order is the most significant bit
+                for (i, &v) in order.iter().enumerate() {
+                    model[v] = bits >> (n - 1 - i) & 1 == 1;
+                }
+                model
+            })
+            .filter(|model| {
+                parts.iter().all(|part| {
+                    part.iter()
+                        .any(|cube| cube.iter().all(|&l| model[var(l)] == (l > 0)))
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lists_the_given_variables_first() {
+        assert_eq!(full_order(&[3, 1, 3], 4), vec![3, 1, 2, 4]);
+        assert_eq!(full_order(&[5], 2), vec![5, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn parses_orders() {
+        assert_eq!(parse_order("c order\n3 1\n\n2\n".as_bytes()), vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn best_cube_and_lazy_search_agree_with_brute_force() {
+        let parts: Vec<Vec<Cube>> = vec![
+            vec![vec![1, -2], vec![-1, 3], vec![2, 4]],
+            vec![vec![-3], vec![4, -1]],
+            vec![vec![2, -4], vec![-2]],
+        ];
+        for order in [vec![1, 2, 3, 4], vec![4, 2, 3, 1], vec![3, 1, 4, 2]] {
+            let all = models(&parts, &order);
+            for max in [false, true] {
+                let expected = if max { all.last() } else { all.first() };
+                assert_eq!(lazy_search(&parts, &order, max).as_ref(), expected);
+                // The cross product of the parts, for best_cube
+                let crossed: Vec<Cube> = parts[0]
+                    .iter()
+                    .flat_map(|a| {
+                        parts[1]
+                            .iter()
+                            .map(move |b| [a.clone(), b.clone()].concat())
+                    })
+                    .flat_map(|ab| {
+                        parts[2]
+                            .iter()
+                            .map(move |c| [ab.clone(), c.clone()].concat())
+                    })
+                    .filter(|cube| !cube.iter().any(|l| cube.contains(&-l)))
+                    .collect();
+                let best = best_cube(&crossed, &order, max).map(|cube| complete(cube, 4, max));
+                assert_eq!(best.as_ref(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn lazy_search_runs_deeper_than_the_stack() {
+        let order: Vec<usize> = (1..=1_000_000).collect();
+        let parts = vec![vec![vec![-1, 1_000_000]]];
+        let model = lazy_search(&parts, &order, true).unwrap();
+        assert!(!model[1] && model[2] && model[1_000_000]);
+        assert!(lazy_search(&[vec![]], &order, false).is_none());
+    }
+}
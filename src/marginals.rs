@@ -0,0 +1,344 @@
+// Marginal probabilities P(v = true | F) under independent literal weights:
+// a model's weight is the product of the weights of its true literals, so
+// each variable is true with probability w(v) / (w(v) + w(-v)) a priori.
+//
+// The exact mode makes the cubes disjoint (dnf::disjoint) and sums their
+// probabilities. The Monte Carlo mode uses the Karp-Luby trick instead: draw
+// a cube proportionally to its probability and a model of it, and keep the
+// model only if that cube is the first one it satisfies, which samples the
+// models of the union by their weight without making anything disjoint.
+use std::io::BufRead;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::cnf::{parse_lines, var, Lit};
+use crate::dnf::{self, Cube};
+
+// The Monte Carlo samples are split into this many independently seeded runs
+const MC_CHUNKS: u64 = 64;
+
+/**
+ * Literal weights, 1 for both literals of a variable unless given (which is
+ * the uniform distribution over the models).
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Weights {
+    pos: Vec<f64>,
+    neg: Vec<f64>,
+}
+
+impl Weights {
+    pub fn set(&mut self, l: Lit, weight: f64) {
+        if var(l) >= self.pos.len() {
+            self.pos.resize(var(l) + 1, 1.0);
+            self.neg.resize(var(l) + 1, 1.0);
+        }
+        if l > 0 {
+            self.pos[var(l)] = weight;
+        } else {
+            self.neg[var(l)] = weight;
+        }
+    }
+
+    pub fn weight(&self, l: Lit) -> f64 {
+        let weights = if l > 0 { &self.pos } else { &self.neg };
+        weights.get(var(l)).copied().unwrap_or(1.0)
+    }
+
+    // The a priori probability of l being true
+    pub fn prob(&self, l: Lit) -> f64 {
+        self.weight(l) / (self.weight(l) + self.weight(-l))
+    }
+}
+
+/**
+ * Spec: parses a weights file, one "<lit> <weight>" pair per line ("c"
+ * comment and blank lines are skipped). Weights must be non-negative and not
+ * both zero for a variable.
+ */
+pub fn parse_weights<R: BufRead>(reader: R) -> Weights {
+    let mut weights = Weights::default();
+    parse_lines(reader, "WEIGHT", |fields| match fields {
+        [l, weight] => {
+            let l: Lit = l.parse().ok().filter(|&l| l != 0)?;
+            weights.set(l, weight.parse().ok().filter(|&w: &f64| w >= 0.0)?);
+            Some(())
+        }
+        _ => None,
+    });
+    weights
+}
+
+// ln P(cube), the probability of a random model extending it
+fn log_prob(cube: &[Lit], weights: &Weights) -> f64 {
+    cube.iter().map(|&l| weights.prob(l).ln()).sum()
+}
+
+/**
+ * Spec: P(v = true | some cube holds) for v in 1..=num_vars (index 0 unused),
+ * exactly, and how many disjoint cubes that took. None if the cubes have
+ * probability 0 (e.g. there are none).
+ */
+pub fn exact(cubes: &[Cube], num_vars: usize, weights: &Weights) -> (Option<Vec<f64>>, u64) {
+    let cubes = dnf::disjoint(cubes);
+    let disjoint = cubes.len() as u64;
+    let log_probs: Vec<f64> = cubes.iter().map(|cube| log_prob(cube, weights)).collect();
+    // Scaled by the largest cube probability, so long cubes do not underflow
+    let top = log_probs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let probs: Vec<f64> = log_probs.iter().map(|lp| (lp - top).exp()).collect();
+    let total: f64 = probs.iter().sum();
+    if total.is_nan() || total <= 0.0 {
+        return (None, disjoint);
+    }
+    // pos[v]: the cubes fixing v true, fixed[v]: the cubes fixing v at all
+    let mut pos = vec![0.0; num_vars + 1];
+    let mut fixed = vec![0.0; num_vars + 1];
+    for (cube, p) in cubes.iter().zip(&probs) {
+        for &l in cube {
+            if var(l) > num_vars {
+                continue;
+            }
+            fixed[var(l)] += p;
+            if l > 0 {
+                pos[var(l)] += p;
+            }
+        }
+    }
+    // Where v is free it is true with its prior probability
+    let mut marginals: Vec<f64> = (0..=num_vars)
+        .map(|v| (pos[v] + weights.prob(v as Lit) * (total - fixed[v])) / total)
+        .collect();
+    marginals[0] = 0.0;
+    (Some(marginals), disjoint)
+}
+
+// SplitMix64, small and good enough for sampling
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn satisfies(model: &[bool], cube: &[Lit]) -> bool {
+    cube.iter().all(|&l| model[var(l)] == (l > 0))
+}
+
+/**
+ * Spec: estimates the marginals from about `samples` Karp-Luby trials, the
+ * same for a given seed whatever the thread count, and how many trials were
+ * accepted. Variables in no cube get their prior probability. None if the
+ * cubes have probability 0.
+ */
+pub fn monte_carlo(
+    cubes: &[Cube],
+    num_vars: usize,
+    weights: &Weights,
+    samples: u64,
+    seed: u64,
+) -> (Option<Vec<f64>>, u64) {
+    let cubes = dnf::normalize(cubes.to_vec());
+    let num_vars = num_vars.max(cubes.iter().flatten().map(|&l| var(l)).max().unwrap_or(0));
+    let log_probs: Vec<f64> = cubes.iter().map(|cube| log_prob(cube, weights)).collect();
+    let top = log_probs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    // cumulative[i] = the scaled probability of cubes[..=i]
+    let cumulative: Vec<f64> = log_probs
+        .iter()
+        .scan(0.0, |acc, lp| {
+            *acc += (lp - top).exp();
+            Some(*acc)
+        })
+        .collect();
+    let total = cumulative.last().copied().unwrap_or(0.0);
+    if total.is_nan() || total <= 0.0 {
+        return (None, 0);
+    }
+
+    let (accepted, pos) = (0..MC_CHUNKS)
+        .into_par_iter()
+        .map(|chunk| {
+            let mut rng = SplitMix64(seed ^ chunk.wrapping_mul(0xa076_1d64_78bd_642f));
+            let trials = samples / MC_CHUNKS + u64::from(chunk < samples % MC_CHUNKS);
+            let mut accepted = 0u64;
+            let mut pos = vec![0u64; num_vars + 1];
+            let mut model = vec![false; num_vars + 1];
+            for _ in 0..trials {
+                let target = rng.next_f64() * total;
+                let i = cumulative
+                    .partition_point(|&c| c <= target)
+                    .min(cubes.len() - 1);
+                for (v, val) in model.iter_mut().enumerate().skip(1) {
+                    *val = rng.next_f64() < weights.prob(v as Lit);
+                }
+                for &l in &cubes[i] {
+                    model[var(l)] = l > 0;
+                }
+                // Count each model once, for the first cube it satisfies
+                if cubes[..i].iter().any(|cube| satisfies(&model, cube)) {
+                    continue;
+                }
+                accepted += 1;
+                for (v, &val) in model.iter().enumerate().skip(1) {
+                    pos[v] += u64::from(val);
+                }
+            }
+            (accepted, pos)
+        })
+        .reduce(
+            || (0, vec![0; num_vars + 1]),
+            |(acc_a, pos_a), (acc_b, pos_b)| {
+                let pos = pos_a.iter().zip(&pos_b).map(|(a, b)| a + b).collect();
+                (acc_a + acc_b, pos)
+            },
+        );
+    if accepted == 0 {
+        return (None, 0);
+    }
+
+    let mut mentioned = vec![false; num_vars + 1];
+    for &l in cubes.iter().flatten() {
+        mentioned[var(l)] = true;
+    }
+    let mut marginals: Vec<f64> = (0..=num_vars)
+        .map(|v| {
+            if mentioned[v] {
+                pos[v] as f64 / accepted as f64
+            } else {
+                weights.prob(v as Lit)
+            }
+        })
+        .collect();
+    marginals[0] = 0.0;
+    (Some(marginals), accepted)
+}
+
+/**
+ * Spec: the marginals as a JSON object, "marginals" listing P(v = true) for
+ * v = 1, 2, ... in order
+ */
+pub fn to_json(marginals: &[f64], method: &str) -> String {
+    let values: Vec<String> = marginals
+        .iter()
+        .skip(1)
+        .map(|p| {
+            if p.is_finite() {
+                format!("{p}")
+            } else {
+                String::from("null")
+            }
+        })
+        .collect();
+    format!(
+        "{{\"method\": \"{method}\", \"num_vars\": {}, \"marginals\": [{}]}}",
+        values.len(),
+        values.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // P(v = true | some cube holds) by enumerating the models of 1..=num_vars
+    fn brute_force(cubes: &[Cube], num_vars: usize, weights: &Weights) -> Vec<f64> {
+        let mut total = 0.0;
+        let mut pos = vec![0.0; num_vars + 1];
+        for bits in 0..1u32 << num_vars {
+            let model: Vec<bool> = (0..=num_vars)
+                .map(|v| v > 0 && bits >> (v - 1) & 1 == 1)
+                .collect();
+            if !cubes.iter().any(|cube| satisfies(&model, cube)) {
+                continue;
+            }
+            let weight: f64 = (1..=num_vars)
+                .map(|v| weights.prob(if model[v] { v as Lit } else { -(v as Lit) }))
+                .product();
+            total += weight;
+            for v in 1..=num_vars {
+                if model[v] {
+                    pos[v] += weight;
+                }
+            }
+        }
+        pos.iter().map(|p| p / total).collect()
+    }
+
+    fn weights() -> Weights {
+        parse_weights("c weights\n1 3\n-1 1\n\n-2 0.5\n3 0\n".as_bytes())
+    }
+
+    #[test]
+    fn parses_weights() {
+        let weights = weights();
+        assert_eq!(weights.prob(1), 0.75);
+        assert_eq!(weights.prob(-2), 1.0 / 3.0);
+        assert_eq!(weights.prob(3), 0.0);
+        assert_eq!(weights.prob(4), 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "CRITICAL ERROR PARSING WEIGHT")]
+    fn rejects_negative_weights() {
+        parse_weights("1 -2\n".as_bytes());
+    }
+
+    #[test]
+    fn exact_matches_brute_force() {
+        let cube_sets: [Vec<Cube>; 4] = [
+            vec![vec![]],
+            vec![vec![1, -2], vec![2, 4], vec![-1, 3, -4]],
+            vec![vec![1], vec![1, 2], vec![-3, 2]],
+            vec![vec![-1, 2, 3, 4], vec![2, -4]],
+        ];
+        for (cubes, weights) in cube_sets
+            .iter()
+            .flat_map(|c| [(c, Weights::default()), (c, weights())])
+        {
+            let (marginals, disjoint) = exact(cubes, 4, &weights);
+            assert!(disjoint >= 1);
+            let expected = brute_force(cubes, 4, &weights);
+            for (a, b) in marginals.unwrap().iter().zip(&expected).skip(1) {
+                assert!((a - b).abs() < 1e-9, "{cubes:?}: {a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn zero_probability_has_no_marginals() {
+        assert_eq!(exact(&[], 2, &Weights::default()), (None, 0));
+        // Variable 3 is never true
+        assert!(exact(&[vec![3]], 3, &weights()).0.is_none());
+        assert!(monte_carlo(&[vec![3]], 3, &weights(), 100, 0).0.is_none());
+    }
+
+    #[test]
+    fn monte_carlo_converges_and_repeats() {
+        let cubes = vec![vec![1, -2], vec![2, 4], vec![-1, 3, -4]];
+        let weights = weights();
+        let (marginals, accepted) = monte_carlo(&cubes, 4, &weights, 200_000, 7);
+        assert!(accepted > 0 && accepted <= 200_000);
+        let expected = brute_force(&cubes, 4, &weights);
+        for (a, b) in marginals.as_ref().unwrap().iter().zip(&expected).skip(1) {
+            assert!((a - b).abs() < 0.01, "{a} vs {b}");
+        }
+        assert_eq!(monte_carlo(&cubes, 4, &weights, 200_000, 7).0, marginals);
+    }
+
+    #[test]
+    fn writes_json() {
+        assert_eq!(
+            to_json(&[0.0, 0.5, 1.0, f64::NAN], "exact"),
+            "{\"method\": \"exact\", \"num_vars\": 3, \"marginals\": [0.5, 1, null]}"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// Minimal unsatisfiable subsets by QuickXplain (Junker 2004).
+//
+// The constraints are split in halves recursively; a half is only searched
+// for conflict members once the other half's members found so far are known
+// not to be inconsistent on their own, so a MUS of size k out of n costs
+// O(k log(n / k)) consistency checks instead of the n of plain deletion.
+
+/**
+ * Spec: a minimal subset of constraints that is inconsistent, given a
+ * consistency check over subsets (called with the subset's elements).
+ * Every proper subset of the result is consistent, so it is empty if the
+ * check fails on no constraints at all (whatever it always includes is
+ * inconsistent by itself). None if constraints as a whole are consistent.
+ */
+pub fn quickxplain<F: FnMut(&[usize]) -> bool>(
+    constraints: &[usize],
+    mut consistent: F,
+) -> Option<Vec<usize>> {
+    if consistent(constraints) {
+        return None;
+    }
+    // qx assumes the background alone is consistent
+    if !consistent(&[]) {
+        return Some(Vec::new());
+    }
+    let mut background = Vec::new();
+    Some(qx(&mut background, false, constraints, &mut consistent))
+}
+
+// The conflict members of c, given that background + c is inconsistent.
+// added: background just grew, so it may already be inconsistent on its own.
+fn qx<F: FnMut(&[usize]) -> bool>(
+    background: &mut Vec<usize>,
+    added: bool,
+    c: &[usize],
+    consistent: &mut F,
+) -> Vec<usize> {
+    if added && !consistent(background) {
+        return Vec::new();
+    }
+    if c.len() <= 1 {
+        return c.to_vec();
+    }
+    let (c1, c2) = c.split_at(c.len() / 2);
+    let len = background.len();
+
+    background.extend_from_slice(c1);
+    let delta2 = qx(background, !c1.is_empty(), c2, consistent);
+    background.truncate(len);
+
+    background.extend_from_slice(&delta2);
+    let mut delta1 = qx(background, !delta2.is_empty(), c1, consistent);
+    background.truncate(len);
+
+    delta1.extend(delta2);
+    delta1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cnf::Lit;
+    use crate::preprocess::testing::{assignments, satisfies};
+
+    // Subsets of the clauses over 1..=num_vars, checked by brute force
+    fn check(clauses: &[Vec<Lit>], num_vars: usize) -> impl FnMut(&[usize]) -> bool + '_ {
+        move |subset| {
+            let sub: Vec<Vec<Lit>> = subset.iter().map(|&i| clauses[i].clone()).collect();
+            assignments(num_vars).any(|model| satisfies(&model, &sub))
+        }
+    }
+
+    #[test]
+    fn consistent_constraints_have_no_core() {
+        let clauses = vec![vec![1, 2], vec![-1]];
+        assert_eq!(quickxplain(&[0, 1], check(&clauses, 2)), None);
+        assert_eq!(quickxplain(&[], check(&clauses, 2)), None);
+    }
+
+    #[test]
+    fn finds_a_minimal_core() {
+        let clauses = vec![
+            vec![1, 2],
+            vec![3],
+            vec![-1],
+            vec![2, 3],
+            vec![-2],
+            vec![-3, 4],
+            vec![1, -4],
+        ];
+        let all: Vec<usize> = (0..clauses.len()).collect();
+        let mut core = quickxplain(&all, check(&clauses, 4)).unwrap();
+        core.sort_unstable();
+        assert!(!check(&clauses, 4)(&core));
+        for i in 0..core.len() {
+            let mut smaller = core.clone();
+            smaller.remove(i);
+            assert!(check(&clauses, 4)(&smaller), "{core:?} is not minimal");
+        }
+    }
+
+    #[test]
+    fn an_inconsistent_background_gives_an_empty_core() {
+        // The check always includes 1 and -1
+        let mut checks = Vec::new();
+        let core = quickxplain(&[0, 1, 2], |subset| {
+            checks.push(subset.to_vec());
+            false
+        });
+        assert_eq!(core, Some(vec![]));
+        assert_eq!(checks, vec![vec![0, 1, 2], vec![]]);
+    }
+}
@@ -0,0 +1,372 @@
+// Linear pseudo-Boolean problems in the OPB format of the PB competitions:
+//   * #variable= 3 #constraint= 2
+//   min: +1 x1 +2 ~x3 ;
+//   +1 x1 +2 x2 >= 3 ;
+//   -1 x1 +1 x3 = 0 ;
+// Every constraint is normalized to positive coefficients over literals and
+// expanded into disjoint cubes by enumerating its satisfying partial sums.
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::cnf::{var, Lit, Model};
+use crate::dnf::{Costs, Cube};
+
+// Sum of coef * lit, where a literal counts 1 when true and 0 when false
+pub type PbSum = Vec<(i64, Lit)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PbKind {
+    AtLeast,
+    Equal,
+}
+
+// terms >= rhs or terms = rhs, with positive coefficients on distinct variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PbConstraint {
+    pub terms: PbSum,
+    pub kind: PbKind,
+    pub rhs: i64,
+}
+
+// offset + terms, normalized like the constraints
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Objective {
+    pub terms: PbSum,
+    pub offset: i64,
+}
+
+impl Objective {
+    // The objective as literal costs (without the offset)
+    pub fn costs(&self) -> Costs {
+        let mut costs = Costs::default();
+        for &(coef, l) in &self.terms {
+            costs.add(l, coef);
+        }
+        costs
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OpbProblem {
+    pub num_vars: usize,
+    pub constraints: Vec<PbConstraint>,
+    pub objective: Option<Objective>,
+}
+
+/**
+ * Spec: merges the terms of each variable and rewrites c * l with c < 0 as
+ * c - c * -l. Returns the positive terms and the constant they split off.
+ */
+fn normalize(sum: &PbSum) -> (PbSum, i64) {
+    let mut coefs: HashMap<usize, i64> = HashMap::new();
+    let mut offset = 0;
+    for &(coef, l) in sum {
+        // c * -x = c - c * x
+        if l < 0 {
+            offset += coef;
+            *coefs.entry(var(l)).or_default() -= coef;
+        } else {
+            *coefs.entry(var(l)).or_default() += coef;
+        }
+    }
+    let mut vars: Vec<usize> = coefs.keys().copied().collect();
+    vars.sort_unstable();
+    let mut terms = Vec::new();
+    for v in vars {
+        let coef = coefs[&v];
+        if coef > 0 {
+            terms.push((coef, v as Lit));
+        } else if coef < 0 {
+            offset += coef;
+            terms.push((-coef, -(v as Lit)));
+        }
+    }
+    (terms, offset)
+}
+
+// "x3" or "~x3" as a literal
+fn parse_lit(token: &str) -> Option<Lit> {
+    let (neg, name) = match token.strip_prefix('~') {
+        Some(name) => (true, name),
+        None => (false, token),
+    };
+    let v: Lit = name.strip_prefix('x')?.parse().ok()?;
+    if v <= 0 {
+        return None;
+    }
+    Some(if neg { -v } else { v })
+}
+
+// The terms of a statement, panicking on anything but "coef lit" pairs
+// (% rather than is_multiple_of, which needs Rust 1.87)
+#[allow(clippy::manual_is_multiple_of)]
+fn parse_sum(tokens: &[&str], statement: &str) -> PbSum {
+    if tokens.len() % 2 != 0 {
+        panic!("CRITICAL ERROR PARSING OPB (nonlinear terms are not supported): '{statement}'");
+    }
+    tokens
+        .chunks(2)
+        .map(|pair| {
+            let coef: Option<i64> = pair[0].trim_start_matches('+').parse().ok();
+            match (coef, parse_lit(pair[1])) {
+                (Some(coef), Some(l)) => (coef, l),
+                _ => panic!(
+                    "CRITICAL ERROR PARSING OPB TERM '{} {}': '{statement}'",
+                    pair[0], pair[1]
+                ),
+            }
+        })
+        .collect()
+}
+
+/**
+ * Spec: parses an OPB file. Statements end with ';', lines starting with '*'
+ * are comments ("* #variable= n" gives num_vars). "<=" constraints are
+ * negated into ">=" ones, every constraint and the objective are normalized.
+ */
+pub fn parse_opb<R: Read>(mut reader: R) -> OpbProblem {
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .expect("Failed to read OPB file!");
+    let mut problem = OpbProblem::default();
+    let mut body = String::new();
+    for line in text.lines() {
+        let good_line = line.trim();
+        if let Some(comment) = good_line.strip_prefix('*') {
+            let fields: Vec<&str> = comment.split_whitespace().collect();
+            if let Some(pos) = fields.iter().position(|&f| f == "#variable=") {
+                if let Some(n) = fields.get(pos + 1).and_then(|n| n.parse().ok()) {
+                    problem.num_vars = n;
+                }
+            }
+            continue;
+        }
+        body.push_str(good_line);
+        body.push(' ');
+    }
+
+    for statement in body.split(';') {
+        let statement = statement.trim();
+        if statement.is_empty() {
+            continue;
+        }
+        if let Some(obj) = statement.strip_prefix("min:") {
+            let tokens: Vec<&str> = obj.split_whitespace().collect();
+            let (terms, offset) = normalize(&parse_sum(&tokens, statement));
+            problem.objective = Some(Objective { terms, offset });
+            continue;
+        }
+        let tokens: Vec<&str> = statement.split_whitespace().collect();
+        let op = tokens
+            .iter()
+            .position(|t| matches!(*t, ">=" | "<=" | "="))
+            .unwrap_or_else(|| panic!("CRITICAL ERROR PARSING OPB (no relation): '{statement}'"));
+        if tokens.len() != op + 2 {
+            panic!("CRITICAL ERROR PARSING OPB (bad right hand side): '{statement}'");
+        }
+        let mut rhs: i64 = tokens[op + 1]
+            .trim_start_matches('+')
+            .parse()
+            .unwrap_or_else(|_| panic!("CRITICAL ERROR PARSING OPB RHS: '{statement}'"));
+        let mut sum = parse_sum(&tokens[..op], statement);
+        if tokens[op] == "<=" {
+            sum.iter_mut().for_each(|(coef, _)| *coef = -*coef);
+            rhs = -rhs;
+        }
+        let kind = if tokens[op] == "=" {
+            PbKind::Equal
+        } else {
+            PbKind::AtLeast
+        };
+        let (terms, offset) = normalize(&sum);
+        problem.constraints.push(PbConstraint {
+            terms,
+            kind,
+            rhs: rhs - offset,
+        });
+    }
+
+    let objective_terms = problem.objective.iter().flat_map(|obj| &obj.terms);
+    for (_, l) in problem
+        .constraints
+        .iter()
+        .flat_map(|c| &c.terms)
+        .chain(objective_terms)
+    {
+        problem.num_vars = problem.num_vars.max(var(*l));
+    }
+    problem
+}
+
+// Depth first enumeration state for the cubes of one constraint
+struct Enumerator<'a> {
+    // Sorted by decreasing coefficient, so the sums grow fastest first
+    terms: &'a [(i64, Lit)],
+    // suffix[i] = the sum of the coefficients of terms[i..]
+    suffix: Vec<i64>,
+    kind: PbKind,
+    rhs: i64,
+    cube: Cube,
+    ret_vec: Vec<Cube>,
+}
+
+impl Enumerator<'_> {
+    fn enumerate(&mut self, i: usize, sum: i64) {
+        if sum + self.suffix[i] < self.rhs {
+            return;
+        }
+        match self.kind {
+            // Already reached, the remaining literals are free
+            PbKind::AtLeast if sum >= self.rhs => {
+                self.ret_vec.push(self.cube.clone());
+                return;
+            }
+            PbKind::Equal if sum > self.rhs => return,
+            // Reached exactly, any further true literal overshoots
+            PbKind::Equal if sum == self.rhs => {
+                let mut cube = self.cube.clone();
+                cube.extend(self.terms[i..].iter().map(|&(_, l)| -l));
+                self.ret_vec.push(cube);
+                return;
+            }
+            _ => {}
+        }
+        // sum < rhs <= sum + suffix[i], so terms[i] exists
+        let (coef, l) = self.terms[i];
+        self.cube.push(l);
+        self.enumerate(i + 1, sum + coef);
+        self.cube.pop();
+        self.cube.push(-l);
+        self.enumerate(i + 1, sum);
+        self.cube.pop();
+    }
+}
+
+/**
+ * Spec: the models of c as disjoint cubes over its literals
+ */
+pub fn constraint_cubes(c: &PbConstraint) -> Vec<Cube> {
+    let mut terms = c.terms.clone();
+    terms.sort_unstable_by_key(|&(coef, l)| (std::cmp::Reverse(coef), var(l)));
+    let mut suffix = vec![0; terms.len() + 1];
+    for i in (0..terms.len()).rev() {
+        suffix[i] = suffix[i + 1] + terms[i].0;
+    }
+    let mut enumerator = Enumerator {
+        terms: &terms,
+        suffix,
+        kind: c.kind,
+        rhs: c.rhs,
+        cube: Vec::new(),
+        ret_vec: Vec::new(),
+    };
+    enumerator.enumerate(0, 0);
+    enumerator.ret_vec
+}
+
+// PB competition style "v x1 -x2 x3" model line
+pub fn model_line(model: &Model) -> String {
+    let mut line = String::from("v");
+    for (v, val) in model.iter().enumerate().skip(1) {
+        if *val {
+            line.push_str(&format!(" x{v}"));
+        } else {
+            line.push_str(&format!(" -x{v}"));
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sum >= rhs or sum = rhs in the assignment given by bits (bit v - 1 for x_v)
+    fn holds(c: &PbConstraint, bits: u32) -> bool {
+        let sum: i64 = c
+            .terms
+            .iter()
+            .filter(|&&(_, l)| (bits >> (var(l) - 1) & 1 == 1) == (l > 0))
+            .map(|&(coef, _)| coef)
+            .sum();
+        match c.kind {
+            PbKind::AtLeast => sum >= c.rhs,
+            PbKind::Equal => sum == c.rhs,
+        }
+    }
+
+    #[test]
+    fn normalizes_negative_coefficients() {
+        // -2 x1 + 3 ~x2 + 1 x1 = 3 - x1 - 3 x2 = -1 + ~x1 + 3 ~x2
+        let (terms, offset) = normalize(&vec![(-2, 1), (3, -2), (1, 1)]);
+        assert_eq!(terms, vec![(1, -1), (3, -2)]);
+        assert_eq!(offset, -1);
+    }
+
+    #[test]
+    fn parses_constraints_and_objective() {
+        let problem = parse_opb(
+            "* #variable= 4 #constraint= 2\nmin: +1 x1 -2 x3 ;\n+1 x1 +2 x2\n >= 2 ;\n+1 x1 +1 ~x3 <= 1 ;\n"
+                .as_bytes(),
+        );
+        assert_eq!(problem.num_vars, 4);
+        assert_eq!(
+            problem.objective,
+            Some(Objective {
+                terms: vec![(1, 1), (2, -3)],
+                offset: -2
+            })
+        );
+        assert_eq!(
+            problem.constraints,
+            vec![
+                PbConstraint {
+                    terms: vec![(1, 1), (2, 2)],
+                    kind: PbKind::AtLeast,
+                    rhs: 2
+                },
+                // -x1 - ~x3 >= -1 is ~x1 + x3 >= 1
+                PbConstraint {
+                    terms: vec![(1, -1), (1, 3)],
+                    kind: PbKind::AtLeast,
+                    rhs: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cubes_are_disjoint_and_cover_the_models() {
+        let constraints = [
+            PbConstraint {
+                terms: vec![(3, 1), (2, -2), (2, 3), (1, 4)],
+                kind: PbKind::AtLeast,
+                rhs: 4,
+            },
+            PbConstraint {
+                terms: vec![(1, 1), (2, 2), (3, -3), (1, 4)],
+                kind: PbKind::Equal,
+                rhs: 3,
+            },
+            PbConstraint {
+                terms: vec![(1, 1), (1, 2)],
+                kind: PbKind::AtLeast,
+                rhs: 3,
+            },
+        ];
+        for c in &constraints {
+            let cubes = constraint_cubes(c);
+            for bits in 0..16u32 {
+                let matching = cubes
+                    .iter()
+                    .filter(|cube| {
+                        cube.iter()
+                            .all(|&l| (bits >> (var(l) - 1) & 1 == 1) == (l > 0))
+                    })
+                    .count();
+                assert_eq!(matching, usize::from(holds(c, bits)), "{c:?} {bits:b}");
+            }
+        }
+    }
+}
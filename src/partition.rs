@@ -0,0 +1,224 @@
+// Clause clustering for building conjunction trees.
+//
+// Clauses are the vertices of a hypergraph and every variable is a hyperedge
+// joining the clauses it occurs in. A bisection that cuts few hyperedges keeps
+// clauses that share variables on the same side, so their (mostly conflicting)
+// cross products get pruned before the two halves are crossed together.
+
+// Number of refinement sweeps run after the initial split
+const REFINE_PASSES: usize = 4;
+
+/**
+ * Spec: splits the clause indices 0..clauses.len() into two non-empty halves
+ * of (nearly) equal size, trying to minimise the number of variables that
+ * occur on both sides.
+ * Invariant: clauses.len() >= 2, variables are 1-indexed (0 is ignored)
+ */
+pub fn bisect(clauses: &[Vec<u32>]) -> (Vec<usize>, Vec<usize>) {
+    let n = clauses.len();
+    // Each clause should mention a variable once, repeated literals ("3 3 0")
+    // and tautologies ("1 -1 0") would otherwise be counted twice
+    let clauses: Vec<Vec<u32>> = clauses
+        .iter()
+        .map(|c| {
+            let mut vars: Vec<u32> = c.iter().copied().filter(|v| *v != 0).collect();
+            vars.sort_unstable();
+            vars.dedup();
+            vars
+        })
+        .collect();
+    let clauses = &clauses[..];
+    let num_vars = clauses
+        .iter()
+        .flat_map(|c| c.iter())
+        .copied()
+        .max()
+        .unwrap_or(0) as usize;
+
+    // occurs[v] = every clause that mentions variable v
+    let mut occurs: Vec<Vec<usize>> = vec![Vec::new(); num_vars + 1];
+    for (idx, clause) in clauses.iter().enumerate() {
+        for &v in clause {
+            occurs[v as usize].push(idx);
+        }
+    }
+
+    let mut side = initial_split(clauses, &occurs);
+    refine(clauses, &occurs, &mut side);
+
+    let mut left = Vec::with_capacity(n / 2 + 1);
+    let mut right = Vec::with_capacity(n / 2 + 1);
+    for (idx, in_right) in side.into_iter().enumerate() {
+        if in_right {
+            right.push(idx);
+        } else {
+            left.push(idx);
+        }
+    }
+    (left, right)
+}
+
+/**
+ * Grows the left half by breadth-first search over shared variables, so each
+ * half starts out as a connected-ish region of the clause graph.
+ * Returns true for clauses placed on the right.
+ */
+fn initial_split(clauses: &[Vec<u32>], occurs: &[Vec<usize>]) -> Vec<bool> {
+    let n = clauses.len();
+    let target = n / 2;
+    let mut side = vec![true; n];
+    let mut seen = vec![false; n];
+    let mut var_seen = vec![false; occurs.len()];
+    let mut queue = std::collections::VecDeque::new();
+    let mut taken = 0;
+
+    for start in 0..n {
+        if taken == target {
+            break;
+        }
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        queue.push_back(start);
+        while let Some(idx) = queue.pop_front() {
+            if taken == target {
+                break;
+            }
+            side[idx] = false;
+            taken += 1;
+            for &v in &clauses[idx] {
+                let v = v as usize;
+                if var_seen[v] {
+                    continue;
+                }
+                var_seen[v] = true;
+                for &next in &occurs[v] {
+                    if !seen[next] {
+                        seen[next] = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        queue.clear();
+    }
+    side
+}
+
+/**
+ * Greedy Fiduccia-Mattheyses style refinement: sweeps the clauses and moves
+ * any clause whose move strictly reduces the cut, as long as the halves stay
+ * within the balance tolerance.
+ */
+fn refine(clauses: &[Vec<u32>], occurs: &[Vec<usize>], side: &mut [bool]) {
+    let n = clauses.len();
+    let tolerance = std::cmp::max(1, n / 20);
+    // count[v] = (occurrences on the left, occurrences on the right)
+    let mut count: Vec<(usize, usize)> = vec![(0, 0); occurs.len()];
+    for (v, occ) in occurs.iter().enumerate() {
+        for &idx in occ {
+            if side[idx] {
+                count[v].1 += 1;
+            } else {
+                count[v].0 += 1;
+            }
+        }
+    }
+    let mut left_size = side.iter().filter(|s| !**s).count();
+
+    for _ in 0..REFINE_PASSES {
+        let mut moved = false;
+        for idx in 0..n {
+            let from_right = side[idx];
+            let new_left = if from_right {
+                left_size + 1
+            } else {
+                left_size - 1
+            };
+            if new_left == 0 || new_left == n || new_left.abs_diff(n - new_left) > 2 * tolerance {
+                continue;
+            }
+            let mut gain: i64 = 0;
+            for &v in &clauses[idx] {
+                let (l, r) = count[v as usize];
+                let (here, there) = if from_right { (r, l) } else { (l, r) };
+                if here == 1 && there > 0 {
+                    // Last occurrence on this side, the variable stops being cut
+                    gain += 1;
+                } else if here > 1 && there == 0 {
+                    // First occurrence on the other side, the variable becomes cut
+                    gain -= 1;
+                }
+            }
+            if gain <= 0 {
+                continue;
+            }
+            for &v in &clauses[idx] {
+                let entry = &mut count[v as usize];
+                if from_right {
+                    entry.1 -= 1;
+                    entry.0 += 1;
+                } else {
+                    entry.0 -= 1;
+                    entry.1 += 1;
+                }
+            }
+            side[idx] = !from_right;
+            left_size = new_left;
+            moved = true;
+        }
+        if !moved {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The variables occurring on both sides
+    fn cut(clauses: &[Vec<u32>], left: &[usize], right: &[usize]) -> usize {
+        let vars = |half: &[usize]| -> std::collections::HashSet<u32> {
+            half.iter()
+                .flat_map(|&i| clauses[i].iter().copied())
+                .collect()
+        };
+        vars(left).intersection(&vars(right)).count()
+    }
+
+    #[test]
+    fn halves_partition_the_clauses() {
+        for n in 2..40u32 {
+            let clauses: Vec<Vec<u32>> =
+                (0..n).map(|i| vec![i % 7 + 1, (i * 3) % 11 + 1]).collect();
+            let (left, right) = bisect(&clauses);
+            assert!(!left.is_empty() && !right.is_empty());
+            let mut all = [left.clone(), right.clone()].concat();
+            all.sort_unstable();
+            assert_eq!(all, (0..n as usize).collect::<Vec<_>>());
+            let tolerance = std::cmp::max(1, n as usize / 20);
+            assert!(left.len().abs_diff(right.len()) <= 2 * tolerance + 1);
+        }
+    }
+
+    #[test]
+    fn separates_independent_clusters() {
+        // Two chains over disjoint variables, interleaved
+        let clauses: Vec<Vec<u32>> = (0..20)
+            .map(|i| {
+                let base = if i % 2 == 0 { 0 } else { 100 };
+                vec![base + i / 2 + 1, base + i / 2 + 2]
+            })
+            .collect();
+        let (left, right) = bisect(&clauses);
+        assert_eq!(cut(&clauses, &left, &right), 0);
+    }
+
+    #[test]
+    fn ignores_repeated_variables() {
+        let (left, right) = bisect(&[vec![1, 1], vec![2, 0, 2]]);
+        assert_eq!((left.len(), right.len()), (1, 1));
+    }
+}
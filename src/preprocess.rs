@@ -0,0 +1,322 @@
+// CNF preprocessing run on the parsed clause database before DNF expansion.
+//
+// Every pass only shrinks the clause set handed to the engine and is
+// satisfiability preserving. Whatever a pass removes is recorded on the
+// Reconstruction stack so a model of the smaller CNF can be turned back into a
+// model of the original one.
+pub mod bce;
+pub mod bve;
+pub mod equiv;
+pub mod probe;
+pub mod propagate;
+pub mod simplify;
+
+use std::fmt;
+use std::io::Write;
+
+use crate::cnf::{lit_true, var, Clause, ClauseDb, Lit, Model};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    Units,
+    Pure,
+    Simplify,
+    // Bounded variable elimination, allowing the clause count to grow by the bound
+    Bve(usize),
+    Equiv,
+    Bce,
+    Probe,
+}
+
+/**
+ * Spec: parses a comma separated pass list such as "units,pure,bve=2"
+ */
+pub fn parse_passes(s: &str) -> Vec<Pass> {
+    s.split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| match name {
+            "units" => Pass::Units,
+            "pure" => Pass::Pure,
+            "simplify" => Pass::Simplify,
+            "bve" => Pass::Bve(0),
+            "equiv" => Pass::Equiv,
+            "bce" => Pass::Bce,
+            "probe" => Pass::Probe,
+            _ if name.starts_with("bve=") => Pass::Bve(
+                name["bve=".len()..]
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Expected a growth bound in '{name}'")),
+            ),
+            _ => panic!("Unknown preprocessing pass: '{name}'"),
+        })
+        .collect()
+}
+
+/**
+ * Model reconstruction stack: each entry is a removed clause together with a
+ * witness literal of it. Going through the stack backwards, every clause the
+ * model does not satisfy gets fixed by making its witness true.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Reconstruction {
+    pub stack: Vec<(Lit, Clause)>,
+}
+
+impl Reconstruction {
+    pub fn push(&mut self, witness: Lit, clause: Clause) {
+        self.stack.push((witness, clause));
+    }
+
+    // A literal that is true in every model of the remaining CNF
+    pub fn push_forced(&mut self, l: Lit) {
+        self.push(l, vec![l]);
+    }
+
+    /**
+     * Spec: writes the stack for use outside this program, next to a CNF that
+     * was renumbered with cnf::compact. Format:
+     *   m <new> <old>          variable new of the CNF is variable old of the input
+     *   r <witness> <lits> 0   a stack entry, in input numbering
+     * To rebuild a model: map it back through the m lines (unlisted variables
+     * false), then for each r line from the last to the first, set the witness
+     * true if none of the clause's literals is true.
+     */
+    pub fn write<W: Write>(&self, var_map: &[usize], mut w: W) -> std::io::Result<()> {
+        writeln!(w, "c dnf-sat reconstruction")?;
+        for (new, old) in var_map.iter().enumerate().skip(1) {
+            writeln!(w, "m {new} {old}")?;
+        }
+        for (witness, clause) in &self.stack {
+            write!(w, "r {witness}")?;
+            for l in clause {
+                write!(w, " {l}")?;
+            }
+            writeln!(w, " 0")?;
+        }
+        w.flush()
+    }
+
+    pub fn extend(&self, model: &mut Model) {
+        for (witness, clause) in self.stack.iter().rev() {
+            if var(*witness) >= model.len() {
+                model.resize(var(*witness) + 1, false);
+            }
+            if !clause.iter().any(|&l| lit_true(model, l)) {
+                model[var(*witness)] = *witness > 0;
+            }
+        }
+    }
+}
+
+// What a pass removed, for the caller to report
+#[derive(Debug, Clone, Copy)]
+pub enum PassStats {
+    Simplify(simplify::SimplifyStats),
+    Bve(usize),
+    Equiv(usize),
+    Bce(usize),
+    Probe(usize),
+}
+
+impl fmt::Display for PassStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassStats::Simplify(stats) => write!(
+                f,
+                "Simplify: removed {} tautologies, {} duplicate literals, {} duplicate clauses, {} subsumed clauses; strengthened {} clauses",
+                stats.tautologies,
+                stats.duplicate_literals,
+                stats.duplicate_clauses,
+                stats.subsumed,
+                stats.strengthened
+            ),
+            PassStats::Bve(n) => write!(f, "BVE: eliminated {n} variables"),
+            PassStats::Equiv(n) => write!(f, "Equiv: substituted {n} variables"),
+            PassStats::Bce(n) => write!(f, "BCE: eliminated {n} blocked clauses"),
+            PassStats::Probe(n) => write!(f, "Probe: {n} failed literals"),
+        }
+    }
+}
+
+/**
+ * Spec: runs the passes in order on db, appending what each one removed to
+ * stats. Returns None if a pass found the CNF unsatisfiable, otherwise the
+ * reduced database and the stack needed to rebuild a full model.
+ */
+pub fn preprocess(
+    mut db: ClauseDb,
+    passes: &[Pass],
+    stats: &mut Vec<PassStats>,
+) -> Option<(ClauseDb, Reconstruction)> {
+    let mut rec = Reconstruction::default();
+    for pass in passes {
+        let removed = match pass {
+            Pass::Units => {
+                if !propagate::propagate_units(&mut db, &mut rec) {
+                    return None;
+                }
+                continue;
+            }
+            Pass::Pure => {
+                propagate::eliminate_pure(&mut db, &mut rec);
+                continue;
+            }
+            Pass::Simplify => PassStats::Simplify(simplify::simplify(&mut db)?),
+            Pass::Bve(growth) => PassStats::Bve(bve::eliminate_vars(&mut db, &mut rec, *growth)?),
+            Pass::Equiv => PassStats::Equiv(equiv::substitute_equivalences(&mut db, &mut rec)?),
+            Pass::Bce => PassStats::Bce(bce::eliminate_blocked(&mut db, &mut rec)),
+            Pass::Probe => PassStats::Probe(probe::probe_failed_literals(&mut db, &mut rec)?),
+        };
+        stats.push(removed);
+    }
+    Some((db, rec))
+}
+
+// Brute force checks and random fixtures shared by the tests
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::Reconstruction;
+    use crate::cnf::{lit_true, Clause, ClauseDb, Lit, Model};
+
+    pub fn db(num_vars: usize, clauses: &[&[Lit]]) -> ClauseDb {
+        ClauseDb {
+            num_vars,
+            clauses: clauses.iter().map(|clause| clause.to_vec()).collect(),
+            ..ClauseDb::default()
+        }
+    }
+
+    // Every assignment to 1..=num_vars
+    pub fn assignments(num_vars: usize) -> impl Iterator<Item = Model> {
+        (0..1u32 << num_vars).map(move |bits| {
+            let mut model = vec![false; num_vars + 1];
+            for (v, val) in model.iter_mut().enumerate().skip(1) {
+                *val = bits >> (v - 1) & 1 == 1;
+            }
+            model
+        })
+    }
+
+    pub fn satisfies(model: &Model, clauses: &[Clause]) -> bool {
+        clauses
+            .iter()
+            .all(|clause| clause.iter().any(|&l| lit_true(model, l)))
+    }
+
+    pub fn is_sat(db: &ClauseDb) -> bool {
+        assignments(db.num_vars).any(|model| satisfies(&model, &db.clauses))
+    }
+
+    /**
+     * The reduced CNF is satisfiable iff the original is, and extending any of
+     * its models through rec gives a model of the original
+     */
+    pub fn assert_reconstructs(original: &ClauseDb, reduced: &ClauseDb, rec: &Reconstruction) {
+        assert_eq!(
+            is_sat(original),
+            is_sat(reduced),
+            "{original:?} -> {reduced:?}"
+        );
+        for mut model in assignments(original.num_vars) {
+            if satisfies(&model, &reduced.clauses) {
+                rec.extend(&mut model);
+                assert!(
+                    satisfies(&model, &original.clauses),
+                    "{original:?} -> {reduced:?}, {rec:?}"
+                );
+            }
+        }
+    }
+
+    // A linear congruential generator, for reproducible random fixtures
+    pub struct Lcg(pub u64);
+
+    impl Lcg {
+        pub fn below(&mut self, bound: u64) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (self.0 >> 33) % bound
+        }
+
+        // len literals over 1..=num_vars, repeats and clashes allowed
+        pub fn lits(&mut self, len: u64, num_vars: usize) -> Vec<Lit> {
+            (0..len)
+                .map(|_| {
+                    let v = 1 + self.below(num_vars as u64) as Lit;
+                    if self.below(2) == 0 {
+                        v
+                    } else {
+                        -v
+                    }
+                })
+                .collect()
+        }
+    }
+
+    // Small random CNFs, the same for a given seed
+    pub fn random_cnfs(seed: u64, count: usize) -> Vec<ClauseDb> {
+        let mut rng = Lcg(seed);
+        (0..count)
+            .map(|_| {
+                let num_vars = 1 + rng.below(6) as usize;
+                let clauses = (0..1 + rng.below(10))
+                    .map(|_| {
+                        let len = 1 + rng.below(3);
+                        rng.lits(len, num_vars)
+                    })
+                    .collect();
+                ClauseDb {
+                    num_vars,
+                    clauses,
+                    ..ClauseDb::default()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{assert_reconstructs, db};
+    use super::*;
+
+    #[test]
+    fn writes_the_stack_in_input_numbering() {
+        let mut rec = Reconstruction::default();
+        rec.push(-7, vec![-7, 3]);
+        rec.push_forced(5);
+        let mut out = Vec::new();
+        rec.write(&[0, 3, 5], &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "c dnf-sat reconstruction\nm 1 3\nm 2 5\nr -7 -7 3 0\nr 5 5 0\n"
+        );
+    }
+
+    #[test]
+    fn extends_models_from_the_last_entry_back() {
+        let mut rec = Reconstruction::default();
+        rec.push(1, vec![1, 2]);
+        rec.push(2, vec![2, -3]);
+        let mut model = vec![false, false, false, true];
+        rec.extend(&mut model);
+        // (2 -3) fixed first makes (1 2) true already
+        assert_eq!(model, vec![false, false, true, true]);
+    }
+
+    #[test]
+    fn all_passes_together_reconstruct_models() {
+        let passes = parse_passes("units,pure,simplify,bve,equiv,bce,probe");
+        for original in testing::random_cnfs(32, 300) {
+            let mut stats = Vec::new();
+            match preprocess(original.clone(), &passes, &mut stats) {
+                Some((reduced, rec)) => assert_reconstructs(&original, &reduced, &rec),
+                None => assert!(!testing::is_sat(&original)),
+            }
+        }
+        let unsat = db(1, &[&[1], &[-1]]);
+        assert!(preprocess(unsat, &passes, &mut Vec::new()).is_none());
+    }
+}
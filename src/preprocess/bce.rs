@@ -0,0 +1,101 @@
+use crate::cnf::{lit_idx, var, Clause, ClauseDb};
+use crate::preprocess::Reconstruction;
+
+// Literals whose negation occurs more often than this are not tried as
+// blocking literals, every such occurrence has to be checked
+const OCC_LIMIT: usize = 256;
+
+/**
+ * Spec: blocked clause elimination. C is blocked on l \in C if every
+ * resolvent of C with a clause containing -l is a tautology; such a C is
+ * removed and pushed onto rec with witness l. Repeats until no clause is
+ * blocked, since removing a clause can block others.
+ * Returns the number of eliminated clauses.
+ */
+pub fn eliminate_blocked(db: &mut ClauseDb, rec: &mut Reconstruction) -> usize {
+    let clauses = std::mem::take(&mut db.clauses);
+    let mut removed = vec![false; clauses.len()];
+    let mut occurs: Vec<Vec<usize>> = vec![Vec::new(); 2 * db.num_vars + 2];
+    for (idx, clause) in clauses.iter().enumerate() {
+        for &l in clause {
+            occurs[lit_idx(l)].push(idx);
+        }
+    }
+    // marks[lit_idx(l)] is true while checking a clause containing l
+    let mut marks = vec![false; 2 * db.num_vars + 2];
+    let mut eliminated = 0;
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (c_idx, clause) in clauses.iter().enumerate() {
+            if removed[c_idx] {
+                continue;
+            }
+            for &l in clause {
+                marks[lit_idx(l)] = true;
+            }
+            let blocking = clause.iter().copied().find(|&l| {
+                let others = &occurs[lit_idx(-l)];
+                others.len() <= OCC_LIMIT
+                    && others.iter().all(|&d_idx| {
+                        removed[d_idx]
+                            || d_idx == c_idx
+                            || clauses[d_idx]
+                                .iter()
+                                .any(|&m| var(m) != var(l) && marks[lit_idx(-m)])
+                    })
+            });
+            for &l in clause {
+                marks[lit_idx(l)] = false;
+            }
+            if let Some(l) = blocking {
+                removed[c_idx] = true;
+                rec.push(l, clause.clone());
+                eliminated += 1;
+                changed = true;
+            }
+        }
+    }
+
+    db.clauses = clauses
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, gone)| !gone)
+        .map(|(clause, _)| clause)
+        .collect::<Vec<Clause>>();
+    eliminated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::testing::{assert_reconstructs, db, random_cnfs};
+
+    #[test]
+    fn removes_blocked_clauses() {
+        // (1 2) is blocked on 1: its only resolvent partner (-1 -2) gives 2 -2
+        let mut cnf = db(3, &[&[1, 2], &[-1, -2], &[2, 3], &[-2, -3]]);
+        let mut rec = Reconstruction::default();
+        assert!(eliminate_blocked(&mut cnf, &mut rec) >= 1);
+        assert!(!cnf.clauses.contains(&vec![1, 2]));
+    }
+
+    #[test]
+    fn keeps_clauses_with_real_resolvents() {
+        let mut cnf = db(2, &[&[1, 2], &[-1, 2], &[1, -2], &[-1, -2]]);
+        let mut rec = Reconstruction::default();
+        assert_eq!(eliminate_blocked(&mut cnf, &mut rec), 0);
+        assert_eq!(cnf.clauses.len(), 4);
+    }
+
+    #[test]
+    fn reconstructs_models() {
+        for original in random_cnfs(31, 300) {
+            let mut reduced = original.clone();
+            let mut rec = Reconstruction::default();
+            eliminate_blocked(&mut reduced, &mut rec);
+            assert_reconstructs(&original, &reduced, &rec);
+        }
+    }
+}
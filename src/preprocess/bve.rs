@@ -0,0 +1,171 @@
+use crate::cnf::{lit_idx, var, Clause, ClauseDb, Lit};
+use crate::preprocess::Reconstruction;
+
+// Variables occurring in more clauses than this are never resolved away, the
+// number of resolvents to try grows with the product of the two sides
+const OCC_LIMIT: usize = 64;
+
+/**
+ * Spec: the resolvent of c (containing l) and d (containing -l) on var(l),
+ * None if it is a tautology
+ */
+fn resolve(c: &[Lit], d: &[Lit], l: Lit) -> Option<Clause> {
+    let mut res: Clause = c
+        .iter()
+        .chain(d.iter())
+        .copied()
+        .filter(|&l2| var(l2) != var(l))
+        .collect();
+    res.sort_unstable_by_key(|&l2| (var(l2), l2));
+    res.dedup();
+    if res.windows(2).any(|w| w[0] == -w[1]) {
+        return None;
+    }
+    Some(res)
+}
+
+// The clauses of an occurrence list that are still in the database
+fn live(occ: &[usize], removed: &[bool]) -> Vec<usize> {
+    let mut idxs: Vec<usize> = occ.iter().copied().filter(|&idx| !removed[idx]).collect();
+    // A repeated literal ("3 3 0") lists its clause twice in a row
+    idxs.dedup();
+    idxs
+}
+
+/**
+ * Spec: bounded variable elimination (Davis-Putnam resolution). A variable x
+ * is eliminated when the non-tautological resolvents of its positive and
+ * negative clauses number at most (#pos + #neg + growth); its clauses are
+ * replaced by the resolvents and pushed onto rec so x can be reconstructed.
+ * Returns the number of eliminated variables, None if an empty resolvent was
+ * derived (UNSAT).
+ */
+pub fn eliminate_vars(db: &mut ClauseDb, rec: &mut Reconstruction, growth: usize) -> Option<usize> {
+    let mut clauses: Vec<Clause> = std::mem::take(&mut db.clauses);
+    let mut removed = vec![false; clauses.len()];
+    let mut occurs: Vec<Vec<usize>> = vec![Vec::new(); 2 * db.num_vars + 2];
+    for (idx, clause) in clauses.iter().enumerate() {
+        for &l in clause {
+            occurs[lit_idx(l)].push(idx);
+        }
+    }
+
+    // Cheapest variables first
+    let mut order: Vec<Lit> = (1..=db.num_vars as Lit)
+        .filter(|&v| !occurs[lit_idx(v)].is_empty() || !occurs[lit_idx(-v)].is_empty())
+        .collect();
+    order.sort_unstable_by_key(|&v| occurs[lit_idx(v)].len() * occurs[lit_idx(-v)].len());
+
+    let mut eliminated = 0;
+    for x in order {
+        let mut pos = live(&occurs[lit_idx(x)], &removed);
+        let mut neg = live(&occurs[lit_idx(-x)], &removed);
+        if pos.len() + neg.len() > 2 * OCC_LIMIT {
+            continue;
+        }
+        // Tautologies over x ("x -x ...") are satisfied either way and must
+        // not be resolved, they are simply dropped with x
+        let taut: Vec<usize> = pos.iter().copied().filter(|i| neg.contains(i)).collect();
+        pos.retain(|i| !taut.contains(i));
+        neg.retain(|i| !taut.contains(i));
+        if pos.len() + neg.len() > OCC_LIMIT {
+            continue;
+        }
+        let bound = pos.len() + neg.len() + growth;
+        let mut resolvents: Vec<Clause> = Vec::new();
+        let mut too_many = false;
+        'outer: for &p in &pos {
+            for &n in &neg {
+                if let Some(res) = resolve(&clauses[p], &clauses[n], x) {
+                    if res.is_empty() {
+                        return None;
+                    }
+                    resolvents.push(res);
+                    if resolvents.len() > bound {
+                        too_many = true;
+                        break 'outer;
+                    }
+                }
+            }
+        }
+        if too_many {
+            continue;
+        }
+
+        for &t in &taut {
+            removed[t] = true;
+        }
+        for &p in &pos {
+            removed[p] = true;
+            rec.push(x, clauses[p].clone());
+        }
+        for &n in &neg {
+            removed[n] = true;
+            rec.push(-x, clauses[n].clone());
+        }
+        for res in resolvents {
+            let idx = clauses.len();
+            for &l in &res {
+                occurs[lit_idx(l)].push(idx);
+            }
+            clauses.push(res);
+            removed.push(false);
+        }
+        eliminated += 1;
+    }
+
+    db.clauses = clauses
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, gone)| !gone)
+        .map(|(clause, _)| clause)
+        .collect();
+    Some(eliminated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::testing::{assert_reconstructs, db, is_sat, random_cnfs};
+
+    #[test]
+    fn resolvents_drop_tautologies() {
+        assert_eq!(resolve(&[1, 2], &[-1, 3, 2], 1), Some(vec![2, 3]));
+        assert_eq!(resolve(&[1, 2], &[-1, -2], 1), None);
+    }
+
+    #[test]
+    fn eliminates_a_definition() {
+        // 3 <-> (1 /\ 2): eliminating 3 leaves (1) and (2) from the last clause
+        let mut cnf = db(3, &[&[-3, 1], &[-3, 2], &[3, -1, -2], &[3]]);
+        let mut rec = Reconstruction::default();
+        assert!(eliminate_vars(&mut cnf, &mut rec, 0).unwrap() >= 1);
+        assert!(cnf
+            .clauses
+            .iter()
+            .all(|clause| !clause.contains(&3) && !clause.contains(&-3)));
+    }
+
+    #[test]
+    fn empty_resolvent_is_unsat() {
+        let mut rec = Reconstruction::default();
+        assert_eq!(
+            eliminate_vars(&mut db(1, &[&[1], &[-1]]), &mut rec, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn reconstructs_models() {
+        for growth in [0, 2] {
+            for original in random_cnfs(29, 300) {
+                let mut reduced = original.clone();
+                let mut rec = Reconstruction::default();
+                match eliminate_vars(&mut reduced, &mut rec, growth) {
+                    Some(_) => assert_reconstructs(&original, &reduced, &rec),
+                    None => assert!(!is_sat(&original)),
+                }
+            }
+        }
+    }
+}
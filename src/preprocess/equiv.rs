@@ -0,0 +1,184 @@
+use crate::cnf::{lit_idx, var, Clause, ClauseDb, Lit};
+use crate::preprocess::Reconstruction;
+
+// % rather than is_multiple_of, which needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn idx_lit(idx: usize) -> Lit {
+    let v = (idx / 2) as Lit;
+    if idx % 2 == 0 {
+        v
+    } else {
+        -v
+    }
+}
+
+/**
+ * Strongly connected components of the graph (iterative Tarjan, the
+ * implication graphs of big instances are too deep for recursion).
+ * Returns comp[node] = component id.
+ */
+fn tarjan(graph: &[Vec<usize>]) -> Vec<usize> {
+    let n = graph.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut comp = vec![usize::MAX; n];
+    let mut stack: Vec<usize> = Vec::new();
+    let mut next_index = 0;
+    let mut num_comps = 0;
+    // (node, position in its successor list)
+    let mut calls: Vec<(usize, usize)> = Vec::new();
+
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        calls.push((root, 0));
+        while let Some((node, pos)) = calls.pop() {
+            if pos == 0 {
+                index[node] = next_index;
+                low[node] = next_index;
+                next_index += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+            if let Some(&succ) = graph[node].get(pos) {
+                calls.push((node, pos + 1));
+                if index[succ] == usize::MAX {
+                    calls.push((succ, 0));
+                } else if on_stack[succ] {
+                    low[node] = low[node].min(index[succ]);
+                }
+                continue;
+            }
+            // All successors done, node is finished
+            if low[node] == index[node] {
+                while let Some(top) = stack.pop() {
+                    on_stack[top] = false;
+                    comp[top] = num_comps;
+                    if top == node {
+                        break;
+                    }
+                }
+                num_comps += 1;
+            }
+            if let Some(&(parent, _)) = calls.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+        }
+    }
+    comp
+}
+
+/**
+ * Spec: equivalent literal substitution. Binary clauses (a \/ b) give the
+ * implications -a -> b and -b -> a; literals in one strongly connected
+ * component of that graph are equivalent, so each is replaced everywhere by
+ * the component's literal over the smallest variable. Substituted variables
+ * are pushed onto rec as x <-> rep.
+ * Returns the number of substituted variables, None if some x and -x are
+ * equivalent (UNSAT).
+ */
+pub fn substitute_equivalences(db: &mut ClauseDb, rec: &mut Reconstruction) -> Option<usize> {
+    let nodes = 2 * db.num_vars + 2;
+    let mut graph: Vec<Vec<usize>> = vec![Vec::new(); nodes];
+    for clause in &db.clauses {
+        let mut lits = clause.clone();
+        lits.sort_unstable();
+        lits.dedup();
+        if let [a, b] = lits[..] {
+            if a != -b {
+                graph[lit_idx(-a)].push(lit_idx(b));
+                graph[lit_idx(-b)].push(lit_idx(a));
+            }
+        }
+    }
+
+    let comp = tarjan(&graph);
+    // rep[comp] = literal over the smallest variable in that component
+    let mut rep: Vec<Lit> = vec![0; nodes];
+    for (idx, &c) in comp.iter().enumerate().skip(2) {
+        let l = idx_lit(idx);
+        if rep[c] == 0 || var(l) < var(rep[c]) {
+            rep[c] = l;
+        }
+    }
+
+    let mut substituted = 0;
+    for v in 1..=db.num_vars {
+        let x = v as Lit;
+        if comp[lit_idx(x)] == comp[lit_idx(-x)] {
+            return None;
+        }
+        let r = rep[comp[lit_idx(x)]];
+        if var(r) != v {
+            rec.push(-x, vec![-x, r]);
+            rec.push(x, vec![x, -r]);
+            substituted += 1;
+        }
+    }
+    if substituted == 0 {
+        return Some(0);
+    }
+
+    let clauses = std::mem::take(&mut db.clauses);
+    db.clauses = clauses
+        .into_iter()
+        .filter_map(|clause| {
+            let mut new_clause: Clause =
+                clause.into_iter().map(|l| rep[comp[lit_idx(l)]]).collect();
+            new_clause.sort_unstable_by_key(|&l| (var(l), l));
+            new_clause.dedup();
+            // Binary clauses inside a component turn into r \/ -r
+            if new_clause.windows(2).any(|w| w[0] == -w[1]) {
+                None
+            } else {
+                Some(new_clause)
+            }
+        })
+        .collect();
+    Some(substituted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::testing::{assert_reconstructs, db, is_sat, random_cnfs};
+
+    #[test]
+    fn finds_cycles_in_the_implication_graph() {
+        // 0 -> 1 -> 2 -> 0 and 2 -> 3
+        let comp = tarjan(&[vec![1], vec![2], vec![0, 3], vec![]]);
+        assert_eq!(comp[0], comp[1]);
+        assert_eq!(comp[1], comp[2]);
+        assert_ne!(comp[2], comp[3]);
+    }
+
+    #[test]
+    fn substitutes_the_smallest_variable() {
+        // 2 <-> -1 and 3 <-> 2
+        let mut cnf = db(4, &[&[1, 2], &[-1, -2], &[-2, 3], &[2, -3], &[3, 4]]);
+        let mut rec = Reconstruction::default();
+        assert_eq!(substitute_equivalences(&mut cnf, &mut rec), Some(2));
+        assert_eq!(cnf.clauses, vec![vec![-1, 4]]);
+    }
+
+    #[test]
+    fn a_literal_equivalent_to_its_negation_is_unsat() {
+        let mut rec = Reconstruction::default();
+        let mut cnf = db(2, &[&[1, 2], &[-1, -2], &[1, -2], &[-1, 2]]);
+        assert_eq!(substitute_equivalences(&mut cnf, &mut rec), None);
+    }
+
+    #[test]
+    fn reconstructs_models() {
+        for original in random_cnfs(30, 300) {
+            let mut reduced = original.clone();
+            let mut rec = Reconstruction::default();
+            match substitute_equivalences(&mut reduced, &mut rec) {
+                Some(_) => assert_reconstructs(&original, &reduced, &rec),
+                None => assert!(!is_sat(&original)),
+            }
+        }
+    }
+}
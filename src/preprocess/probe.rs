@@ -0,0 +1,196 @@
+use crate::cnf::{lit_idx, var, Clause, ClauseDb, Lit};
+use crate::preprocess::{propagate, Reconstruction};
+
+/**
+ * Two watched literal unit propagation, so each probe only touches the
+ * clauses watching the literals it falsifies.
+ */
+struct Propagator {
+    clauses: Vec<Clause>,
+    watches: Vec<Vec<usize>>,
+    value: Vec<Option<bool>>,
+    trail: Vec<Lit>,
+    // Literals of the trail before this index are already propagated
+    qhead: usize,
+}
+
+impl Propagator {
+    // None if the clauses are already conflicting at the top level
+    fn new(db: &ClauseDb) -> Option<Propagator> {
+        let mut prop = Propagator {
+            clauses: Vec::new(),
+            watches: vec![Vec::new(); 2 * db.num_vars + 2],
+            value: vec![None; db.num_vars + 1],
+            trail: Vec::new(),
+            qhead: 0,
+        };
+        let mut units = Vec::new();
+        for clause in &db.clauses {
+            let mut lits = clause.clone();
+            lits.sort_unstable_by_key(|&l| (var(l), l));
+            lits.dedup();
+            if lits.windows(2).any(|w| w[0] == -w[1]) {
+                continue;
+            }
+            match lits.len() {
+                0 => return None,
+                1 => units.push(lits[0]),
+                _ => {
+                    let idx = prop.clauses.len();
+                    prop.watches[lit_idx(lits[0])].push(idx);
+                    prop.watches[lit_idx(lits[1])].push(idx);
+                    prop.clauses.push(lits);
+                }
+            }
+        }
+        for l in units {
+            if !prop.assign(l) {
+                return None;
+            }
+        }
+        Some(prop)
+    }
+
+    fn lit_value(&self, l: Lit) -> Option<bool> {
+        self.value[var(l)].map(|val| val == (l > 0))
+    }
+
+    // Sets l true and propagates, false on conflict
+    fn assign(&mut self, l: Lit) -> bool {
+        match self.lit_value(l) {
+            Some(true) => return true,
+            Some(false) => return false,
+            None => {}
+        }
+        self.value[var(l)] = Some(l > 0);
+        self.trail.push(l);
+        self.propagate()
+    }
+
+    fn propagate(&mut self) -> bool {
+        while self.qhead < self.trail.len() {
+            let false_lit = -self.trail[self.qhead];
+            self.qhead += 1;
+            let watching = std::mem::take(&mut self.watches[lit_idx(false_lit)]);
+            let mut keep = Vec::with_capacity(watching.len());
+            let mut conflict = false;
+            for (pos, &c_idx) in watching.iter().enumerate() {
+                if conflict {
+                    keep.extend_from_slice(&watching[pos..]);
+                    break;
+                }
+                let clause = &mut self.clauses[c_idx];
+                // Keep the falsified watch in slot 1
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if self.value[var(first)] == Some(first > 0) {
+                    keep.push(c_idx);
+                    continue;
+                }
+                let replacement = (2..clause.len()).find(|&k| {
+                    let l = clause[k];
+                    self.value[var(l)] != Some(l < 0)
+                });
+                if let Some(k) = replacement {
+                    clause.swap(1, k);
+                    let new_watch = clause[1];
+                    self.watches[lit_idx(new_watch)].push(c_idx);
+                    continue;
+                }
+                keep.push(c_idx);
+                match self.value[var(first)] {
+                    Some(_) => conflict = true,
+                    None => {
+                        self.value[var(first)] = Some(first > 0);
+                        self.trail.push(first);
+                    }
+                }
+            }
+            self.watches[lit_idx(false_lit)] = keep;
+            if conflict {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Undoes every assignment made after the trail had length len
+    fn backtrack(&mut self, len: usize) {
+        for &l in &self.trail[len..] {
+            self.value[var(l)] = None;
+        }
+        self.trail.truncate(len);
+        self.qhead = len;
+    }
+}
+
+/**
+ * Spec: failed literal probing. Each literal l is assumed and propagated;
+ * if that conflicts, -l holds in every model and is learned as a unit clause
+ * (if both l and -l fail the CNF is UNSAT). The learned units are then
+ * propagated through the database, which pushes them onto rec.
+ * Returns the number of failed literals, None if the CNF was found UNSAT.
+ */
+pub fn probe_failed_literals(db: &mut ClauseDb, rec: &mut Reconstruction) -> Option<usize> {
+    let mut prop = Propagator::new(db)?;
+    let mut learned: Vec<Lit> = Vec::new();
+    for v in 1..=db.num_vars as Lit {
+        for l in [v, -v] {
+            if prop.lit_value(l).is_some() {
+                continue;
+            }
+            let level = prop.trail.len();
+            let ok = prop.assign(l);
+            prop.backtrack(level);
+            if !ok {
+                learned.push(-l);
+                if !prop.assign(-l) {
+                    return None;
+                }
+            }
+        }
+    }
+    let failed = learned.len();
+    if failed == 0 {
+        return Some(0);
+    }
+    db.clauses.extend(learned.into_iter().map(|l| vec![l]));
+    propagate::propagate_units(db, rec).then_some(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::testing::{assert_reconstructs, db, is_sat, random_cnfs};
+
+    #[test]
+    fn learns_failed_literals() {
+        // 1 implies both 2 and -2
+        let mut cnf = db(3, &[&[-1, 2], &[-1, -2], &[1, 3]]);
+        let mut rec = Reconstruction::default();
+        assert_eq!(probe_failed_literals(&mut cnf, &mut rec), Some(1));
+        assert!(cnf.clauses.is_empty());
+        assert_eq!(rec.stack, vec![(-1, vec![-1]), (3, vec![3])]);
+    }
+
+    #[test]
+    fn both_polarities_failing_is_unsat() {
+        let mut cnf = db(2, &[&[1, 2], &[1, -2], &[-1, 2], &[-1, -2]]);
+        let mut rec = Reconstruction::default();
+        assert_eq!(probe_failed_literals(&mut cnf, &mut rec), None);
+    }
+
+    #[test]
+    fn reconstructs_models() {
+        for original in random_cnfs(131, 300) {
+            let mut reduced = original.clone();
+            let mut rec = Reconstruction::default();
+            match probe_failed_literals(&mut reduced, &mut rec) {
+                Some(_) => assert_reconstructs(&original, &reduced, &rec),
+                None => assert!(!is_sat(&original)),
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::cnf::{lit_idx, var, Clause, ClauseDb, Lit};
+use crate::preprocess::Reconstruction;
+
+/**
+ * Spec: unit propagation to fixpoint. Every forced literal is pushed onto rec,
+ * satisfied clauses are dropped and false literals removed from the rest.
+ * Returns false if some clause became empty (UNSAT).
+ */
+pub fn propagate_units(db: &mut ClauseDb, rec: &mut Reconstruction) -> bool {
+    let mut value: Vec<Option<bool>> = vec![None; db.num_vars + 1];
+    let mut occurs: Vec<Vec<usize>> = vec![Vec::new(); 2 * db.num_vars + 2];
+    // Number of distinct literals of each clause that are not false yet
+    let mut open: Vec<usize> = Vec::with_capacity(db.clauses.len());
+    let mut queue: Vec<Lit> = Vec::new();
+
+    for (idx, clause) in db.clauses.iter().enumerate() {
+        let mut lits = clause.clone();
+        lits.sort_unstable();
+        lits.dedup();
+        for &l in &lits {
+            occurs[lit_idx(l)].push(idx);
+        }
+        if lits.is_empty() {
+            return false;
+        }
+        if lits.len() == 1 {
+            queue.push(lits[0]);
+        }
+        open.push(lits.len());
+    }
+
+    let mut satisfied = vec![false; db.clauses.len()];
+    while let Some(l) = queue.pop() {
+        match value[var(l)] {
+            Some(val) if val == (l > 0) => continue,
+            Some(_) => return false,
+            None => {}
+        }
+        value[var(l)] = Some(l > 0);
+        rec.push_forced(l);
+        for &idx in &occurs[lit_idx(l)] {
+            satisfied[idx] = true;
+        }
+        for &idx in &occurs[lit_idx(-l)] {
+            if satisfied[idx] {
+                continue;
+            }
+            open[idx] -= 1;
+            if open[idx] == 0 {
+                return false;
+            }
+            if open[idx] == 1 {
+                let last = db.clauses[idx]
+                    .iter()
+                    .find(|&&l2| value[var(l2)].is_none())
+                    .copied();
+                match last {
+                    Some(l2) => queue.push(l2),
+                    // The remaining literal is true, the clause is satisfied
+                    None => satisfied[idx] = true,
+                }
+            }
+        }
+    }
+
+    let clauses = std::mem::take(&mut db.clauses);
+    db.clauses = clauses
+        .into_iter()
+        .zip(satisfied)
+        .filter(|(_, sat)| !sat)
+        .map(|(clause, _)| {
+            clause
+                .into_iter()
+                .filter(|&l| value[var(l)].is_none())
+                .collect::<Clause>()
+        })
+        .collect();
+    true
+}
+
+/**
+ * Spec: pure literal elimination to fixpoint. A literal whose negation occurs
+ * in no clause is set true and its clauses are dropped, which can make more
+ * literals pure.
+ */
+pub fn eliminate_pure(db: &mut ClauseDb, rec: &mut Reconstruction) {
+    let mut removed = vec![false; db.clauses.len()];
+    let mut count: Vec<usize> = vec![0; 2 * db.num_vars + 2];
+    let mut occurs: Vec<Vec<usize>> = vec![Vec::new(); 2 * db.num_vars + 2];
+    for (idx, clause) in db.clauses.iter().enumerate() {
+        for &l in clause {
+            count[lit_idx(l)] += 1;
+            occurs[lit_idx(l)].push(idx);
+        }
+    }
+
+    let mut candidates: Vec<Lit> = (1..=db.num_vars as Lit).flat_map(|v| [v, -v]).collect();
+    let mut assigned = vec![false; db.num_vars + 1];
+    while let Some(l) = candidates.pop() {
+        if assigned[var(l)] || count[lit_idx(l)] == 0 || count[lit_idx(-l)] != 0 {
+            continue;
+        }
+        assigned[var(l)] = true;
+        rec.push_forced(l);
+        for &idx in &occurs[lit_idx(l)] {
+            if removed[idx] {
+                continue;
+            }
+            removed[idx] = true;
+            for &l2 in &db.clauses[idx] {
+                count[lit_idx(l2)] -= 1;
+                if count[lit_idx(l2)] == 0 {
+                    // -l2 may have just become pure
+                    candidates.push(-l2);
+                }
+            }
+        }
+    }
+
+    let clauses = std::mem::take(&mut db.clauses);
+    db.clauses = clauses
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, gone)| !gone)
+        .map(|(clause, _)| clause)
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::testing::{assert_reconstructs, db, is_sat, random_cnfs};
+
+    #[test]
+    fn units_propagate_to_fixpoint() {
+        let mut cnf = db(4, &[&[1], &[-1, 2], &[-2, 3, 4], &[-3, -1, 4]]);
+        let mut rec = Reconstruction::default();
+        assert!(propagate_units(&mut cnf, &mut rec));
+        assert_eq!(cnf.clauses, vec![vec![3, 4], vec![-3, 4]]);
+        assert_eq!(rec.stack, vec![(1, vec![1]), (2, vec![2])]);
+    }
+
+    #[test]
+    fn units_find_conflicts() {
+        let mut rec = Reconstruction::default();
+        assert!(!propagate_units(
+            &mut db(2, &[&[1], &[-1, 2], &[-2]]),
+            &mut rec
+        ));
+        assert!(!propagate_units(&mut db(1, &[&[]]), &mut rec));
+    }
+
+    #[test]
+    fn pure_literals_cascade() {
+        // -3 is pure; dropping (-3 2) leaves -2 pure, which drops (-2 1)
+        let mut cnf = db(4, &[&[-3, 2], &[-2, 1], &[1, 4], &[-1, -4]]);
+        let mut rec = Reconstruction::default();
+        eliminate_pure(&mut cnf, &mut rec);
+        assert_eq!(cnf.clauses, vec![vec![1, 4], vec![-1, -4]]);
+        assert_eq!(rec.stack, vec![(-3, vec![-3]), (-2, vec![-2])]);
+    }
+
+    #[test]
+    fn passes_reconstruct_models() {
+        for original in random_cnfs(27, 300) {
+            let mut reduced = original.clone();
+            let mut rec = Reconstruction::default();
+            if propagate_units(&mut reduced, &mut rec) {
+                assert_reconstructs(&original, &reduced, &rec);
+            } else {
+                assert!(!is_sat(&original));
+            }
+            let mut reduced = original.clone();
+            let mut rec = Reconstruction::default();
+            eliminate_pure(&mut reduced, &mut rec);
+            assert_reconstructs(&original, &reduced, &rec);
+        }
+    }
+}
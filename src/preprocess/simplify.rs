@@ -0,0 +1,198 @@
+use std::collections::HashSet;
+
+use crate::cnf::{lit_idx, var, Clause, ClauseDb, Lit};
+
+// How many of each kind of redundancy the simplifier removed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimplifyStats {
+    pub tautologies: usize,
+    pub duplicate_literals: usize,
+    pub duplicate_clauses: usize,
+    pub subsumed: usize,
+    pub strengthened: usize,
+}
+
+/**
+ * Spec: removes tautological clauses ("1 -1 2 0"), repeated literals
+ * ("3 3 0"), duplicate clauses and subsumed clauses, and strengthens clauses
+ * by self-subsuming resolution. Models are unchanged.
+ * Returns None if strengthening derived the empty clause (UNSAT).
+ */
+pub fn simplify(db: &mut ClauseDb) -> Option<SimplifyStats> {
+    let mut stats = SimplifyStats::default();
+
+    let mut seen: HashSet<Clause> = HashSet::new();
+    let mut clauses: Vec<Clause> = Vec::with_capacity(db.clauses.len());
+    for mut clause in std::mem::take(&mut db.clauses) {
+        let len = clause.len();
+        // Sorting by variable puts l and -l next to each other
+        clause.sort_unstable_by_key(|&l| (var(l), l));
+        clause.dedup();
+        stats.duplicate_literals += len - clause.len();
+        if clause.windows(2).any(|w| w[0] == -w[1]) {
+            stats.tautologies += 1;
+            continue;
+        }
+        if !seen.insert(clause.clone()) {
+            stats.duplicate_clauses += 1;
+            continue;
+        }
+        clauses.push(clause);
+    }
+    drop(seen);
+
+    if !subsume(&mut clauses, db.num_vars, &mut stats) {
+        return None;
+    }
+    db.clauses = clauses;
+    Some(stats)
+}
+
+/**
+ * Backward subsumption and self-subsuming resolution over occurrence lists.
+ * Every clause C is checked against the clauses sharing its rarest variable:
+ * D is dropped if C is a subset of D, and if C matches D except for one
+ * literal that appears negated in D, that literal is removed from D.
+ * Invariant: every clause is sorted by variable, duplicate and tautology free
+ */
+fn subsume(clauses: &mut Vec<Clause>, num_vars: usize, stats: &mut SimplifyStats) -> bool {
+    let mut occurs: Vec<Vec<usize>> = vec![Vec::new(); 2 * num_vars + 2];
+    for (idx, clause) in clauses.iter().enumerate() {
+        for &l in clause {
+            occurs[lit_idx(l)].push(idx);
+        }
+    }
+    let mut removed = vec![false; clauses.len()];
+    let mut queue: Vec<usize> = (0..clauses.len()).collect();
+    // Short clauses subsume the most, so check them first (popped from the back)
+    queue.sort_unstable_by_key(|&idx| std::cmp::Reverse(clauses[idx].len()));
+
+    while let Some(c_idx) = queue.pop() {
+        if removed[c_idx] {
+            continue;
+        }
+        let pivot = match clauses[c_idx]
+            .iter()
+            .min_by_key(|&&l| occurs[lit_idx(l)].len() + occurs[lit_idx(-l)].len())
+        {
+            Some(&l) => l,
+            None => continue,
+        };
+        let candidates: Vec<usize> = occurs[lit_idx(pivot)]
+            .iter()
+            .chain(occurs[lit_idx(-pivot)].iter())
+            .copied()
+            .collect();
+        for d_idx in candidates {
+            if d_idx == c_idx || removed[d_idx] || removed[c_idx] {
+                continue;
+            }
+            if clauses[c_idx].len() > clauses[d_idx].len() {
+                continue;
+            }
+            match subset_with_flip(&clauses[c_idx], &clauses[d_idx]) {
+                Some(None) => {
+                    removed[d_idx] = true;
+                    stats.subsumed += 1;
+                }
+                Some(Some(l)) => {
+                    // Resolving C and D on l gives D without -l, which subsumes D
+                    clauses[d_idx].retain(|&l2| l2 != -l);
+                    stats.strengthened += 1;
+                    if clauses[d_idx].is_empty() {
+                        return false;
+                    }
+                    // The shorter D may now subsume or strengthen other clauses
+                    queue.push(d_idx);
+                }
+                None => {}
+            }
+        }
+    }
+
+    let old = std::mem::take(clauses);
+    *clauses = old
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, gone)| !gone)
+        .map(|(clause, _)| clause)
+        .collect();
+    true
+}
+
+/**
+ * Some(None) if c is a subset of d, Some(Some(l)) if c with l negated is a
+ * subset of d (for exactly one l), None otherwise.
+ * Invariant: c and d are sorted by variable
+ */
+fn subset_with_flip(c: &[Lit], d: &[Lit]) -> Option<Option<Lit>> {
+    let mut flipped = None;
+    for &l in c {
+        match d.binary_search_by_key(&(var(l), l), |&l2| (var(l2), l2)) {
+            Ok(_) => {}
+            Err(_) => {
+                if flipped.is_some()
+                    || d.binary_search_by_key(&(var(l), -l), |&l2| (var(l2), l2))
+                        .is_err()
+                {
+                    return None;
+                }
+                flipped = Some(l);
+            }
+        }
+    }
+    Some(flipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::testing::{assignments, db, is_sat, random_cnfs, satisfies};
+
+    #[test]
+    fn counts_each_redundancy() {
+        let mut cnf = db(
+            4,
+            &[
+                &[1, -1, 2],
+                &[3, 3],
+                &[2, 4],
+                &[4, 2],
+                &[3, 2, 4],
+                &[-3, 1, 2],
+            ],
+        );
+        let stats = simplify(&mut cnf).unwrap();
+        assert_eq!(stats.tautologies, 1);
+        assert_eq!(stats.duplicate_literals, 1);
+        assert_eq!(stats.duplicate_clauses, 1);
+        // (3) subsumes (3 2 4) and strengthens (-3 1 2) to (1 2)
+        assert_eq!(stats.subsumed, 1);
+        assert_eq!(stats.strengthened, 1);
+        assert_eq!(cnf.clauses, vec![vec![3], vec![2, 4], vec![1, 2]]);
+    }
+
+    #[test]
+    fn strengthening_to_the_empty_clause_is_unsat() {
+        assert!(simplify(&mut db(1, &[&[1], &[-1]])).is_none());
+    }
+
+    #[test]
+    fn keeps_the_models() {
+        for original in random_cnfs(28, 300) {
+            let mut reduced = original.clone();
+            match simplify(&mut reduced) {
+                Some(_) => {
+                    for model in assignments(original.num_vars) {
+                        assert_eq!(
+                            satisfies(&model, &original.clauses),
+                            satisfies(&model, &reduced.clauses),
+                            "{original:?} -> {reduced:?}"
+                        );
+                    }
+                }
+                None => assert!(!is_sat(&original)),
+            }
+        }
+    }
+}
@@ -0,0 +1,518 @@
+// Resolution refutations of the cross product's search tree, in DRAT or LRAT.
+//
+// The tree is searched depth first: a node is a partial cube, it branches on
+// the unassigned literals of the first clause the cube does not satisfy, and a
+// branch conflicts as soon as its literal falsifies some clause. Every
+// conflicting subtree yields a clause falsified by the cube at its root: the
+// falsified clause at a leaf, and at an inner node the resolvent of the
+// branched clause with its children's clauses (unless a child's clause does
+// not mention the branch literal, then it is already falsified one level up).
+// At the root that is the empty clause, and each resolvent is a RUP step whose
+// antecedents are exactly the resolved clauses, which gives the LRAT hints.
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::cnf::{lit_idx, var, Clause, Lit};
+
+// A derived clause; ids 1..=m are the input clauses, derived ones follow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub id: usize,
+    pub lits: Clause,
+    pub hints: Vec<usize>,
+}
+
+struct Refuter<'a> {
+    clauses: &'a [Clause],
+    // occurs[lit_idx(l)] = the clauses containing l
+    occurs: Vec<Vec<usize>>,
+    value: Vec<Option<bool>>,
+    steps: Vec<Step>,
+}
+
+// Ok if some branch satisfies every clause, otherwise the id and literals of a
+// clause falsified by the cube at the node
+type NodeResult = Result<(), (usize, Clause)>;
+
+// A node being searched: it branches on the unassigned literals of clause i
+struct Frame {
+    i: usize,
+    branches: Vec<Lit>,
+    // How many branches were entered, the last one's literal is set
+    entered: usize,
+    resolvent: Clause,
+    hints: Vec<usize>,
+}
+
+impl Refuter<'_> {
+    fn lit_value(&self, l: Lit) -> Option<bool> {
+        self.value[var(l)].map(|val| val == (l > 0))
+    }
+
+    // A clause containing -l with every literal false, l having just been set
+    fn falsified_by(&self, l: Lit) -> Option<usize> {
+        self.occurs[lit_idx(-l)].iter().copied().find(|&c| {
+            self.clauses[c]
+                .iter()
+                .all(|&l2| self.lit_value(l2) == Some(false))
+        })
+    }
+
+    // The node below the current cube from clause i on, or its result if it
+    // is a leaf (every clause satisfied, or one falsified with no branches)
+    fn open(&self, mut i: usize) -> Result<Frame, NodeResult> {
+        while i < self.clauses.len()
+            && self.clauses[i]
+                .iter()
+                .any(|&l| self.lit_value(l) == Some(true))
+        {
+            i += 1;
+        }
+        if i == self.clauses.len() {
+            return Err(Ok(()));
+        }
+        let clause = &self.clauses[i];
+        let mut branches: Vec<Lit> = clause
+            .iter()
+            .copied()
+            .filter(|&l| self.lit_value(l).is_none())
+            .collect();
+        branches.sort_unstable();
+        branches.dedup();
+        if branches.is_empty() {
+            return Err(Err((i + 1, clause.clone())));
+        }
+        // The literals of clause false under the cube stay in the resolvent
+        let resolvent: Clause = clause
+            .iter()
+            .copied()
+            .filter(|&l| self.lit_value(l) == Some(false))
+            .collect();
+        Ok(Frame {
+            i,
+            branches,
+            entered: 0,
+            resolvent,
+            hints: Vec::new(),
+        })
+    }
+
+    /**
+     * Searches the tree depth first over an explicit stack of nodes, as it
+     * can be as deep as there are clauses. Ok if some branch satisfies every
+     * clause (the cube is left assigned), otherwise the id and literals of a
+     * clause falsified by the empty cube.
+     */
+    fn search(&mut self) -> NodeResult {
+        let mut stack: Vec<Frame> = Vec::new();
+        // The result of the node just left, to pass to its parent
+        let mut res: Option<NodeResult> = match self.open(0) {
+            Ok(frame) => {
+                stack.push(frame);
+                None
+            }
+            Err(res) => return res,
+        };
+        while let Some(frame) = stack.last_mut() {
+            if let Some(child) = res.take() {
+                let l = frame.branches[frame.entered - 1];
+                let (id, lits) = match child {
+                    Ok(()) => return Ok(()),
+                    Err(conflict) => conflict,
+                };
+                self.value[var(l)] = None;
+                if !lits.contains(&-l) {
+                    // Already falsified without l, so the node is too
+                    stack.pop();
+                    res = Some(Err((id, lits)));
+                    continue;
+                }
+                frame.hints.push(id);
+                frame
+                    .resolvent
+                    .extend(lits.into_iter().filter(|&l2| l2 != -l));
+            }
+            if frame.entered == frame.branches.len() {
+                let Frame {
+                    i,
+                    mut resolvent,
+                    mut hints,
+                    ..
+                } = stack.pop().unwrap();
+                hints.push(i + 1);
+                resolvent.sort_unstable_by_key(|&l| (var(l), l));
+                resolvent.dedup();
+                let id = self.clauses.len() + self.steps.len() + 1;
+                self.steps.push(Step {
+                    id,
+                    lits: resolvent.clone(),
+                    hints,
+                });
+                res = Some(Err((id, resolvent)));
+                continue;
+            }
+            let l = frame.branches[frame.entered];
+            frame.entered += 1;
+            self.value[var(l)] = Some(l > 0);
+            match self.falsified_by(l) {
+                Some(c) => res = Some(Err((c + 1, self.clauses[c].clone()))),
+                None => match self.open(frame.i + 1) {
+                    Ok(child) => stack.push(child),
+                    Err(child) => res = Some(child),
+                },
+            }
+        }
+        res.expect("the root node has a result")
+    }
+}
+
+/**
+ * Spec: searches the cross product of clauses. Ok with the literals of a
+ * satisfying cube, or Err with a refutation ending in the empty clause.
+ */
+pub fn refute(clauses: &[Clause], num_vars: usize) -> Result<Vec<Lit>, Vec<Step>> {
+    let num_vars = num_vars.max(clauses.iter().flatten().map(|&l| var(l)).max().unwrap_or(0));
+    let mut occurs = vec![Vec::new(); 2 * num_vars + 2];
+    for (c, clause) in clauses.iter().enumerate() {
+        for &l in clause {
+            occurs[lit_idx(l)].push(c);
+        }
+    }
+    let mut refuter = Refuter {
+        clauses,
+        occurs,
+        value: vec![None; num_vars + 1],
+        steps: Vec::new(),
+    };
+    match refuter.search() {
+        Ok(()) => Ok((1..=num_vars)
+            .filter_map(|v| refuter.value[v].map(|val| if val { v as Lit } else { -(v as Lit) }))
+            .collect()),
+        Err((id, lits)) => {
+            // An empty input clause: state the empty clause explicitly
+            if id <= clauses.len() {
+                debug_assert!(lits.is_empty());
+                refuter.steps.push(Step {
+                    id: clauses.len() + 1,
+                    lits: Vec::new(),
+                    hints: vec![id],
+                });
+            }
+            Err(refuter.steps)
+        }
+    }
+}
+
+pub fn write_drat<W: Write>(steps: &[Step], mut w: W) -> std::io::Result<()> {
+    for step in steps {
+        for l in &step.lits {
+            write!(w, "{l} ")?;
+        }
+        writeln!(w, "0")?;
+    }
+    w.flush()
+}
+
+pub fn write_lrat<W: Write>(steps: &[Step], mut w: W) -> std::io::Result<()> {
+    for step in steps {
+        write!(w, "{} ", step.id)?;
+        for l in &step.lits {
+            write!(w, "{l} ")?;
+        }
+        write!(w, "0 ")?;
+        for h in &step.hints {
+            write!(w, "{h} ")?;
+        }
+        writeln!(w, "0")?;
+    }
+    w.flush()
+}
+
+// A partial assignment for checking, over any variables
+#[derive(Default)]
+struct Assignment(HashMap<usize, bool>);
+
+impl Assignment {
+    fn lit_value(&self, l: Lit) -> Option<bool> {
+        self.0.get(&var(l)).map(|&val| val == (l > 0))
+    }
+
+    // Sets l true, false if it already was false
+    fn set(&mut self, l: Lit) -> bool {
+        *self.0.entry(var(l)).or_insert(l > 0) == (l > 0)
+    }
+
+    // Assigns the negation of the lemma, false if it is a tautology
+    fn negate(lemma: &[Lit]) -> Option<Assignment> {
+        let mut assignment = Assignment::default();
+        for &l in lemma {
+            if !assignment.set(-l) {
+                return None;
+            }
+        }
+        Some(assignment)
+    }
+}
+
+// The integers of a proof line, and whether it is a deletion: "d" is the
+// first token in DRAT ("d <lits> 0") and the second in LRAT ("<id> d <ids> 0")
+fn parse_proof_line(line: &str, d_pos: usize) -> Result<(bool, Vec<i64>), String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let deletion = tokens.get(d_pos) == Some(&"d");
+    let mut nums = Vec::new();
+    for (pos, token) in tokens.into_iter().enumerate() {
+        if deletion && pos == d_pos {
+            continue;
+        }
+        nums.push(
+            token
+                .parse()
+                .map_err(|_| format!("bad proof line '{line}'"))?,
+        );
+    }
+    Ok((deletion, nums))
+}
+
+// Literals up to the first 0, and the rest after it
+fn split_zero(nums: &[i64]) -> (Clause, &[i64]) {
+    let end = nums.iter().position(|&n| n == 0).unwrap_or(nums.len());
+    let lits = nums[..end].iter().map(|&n| n as Lit).collect();
+    (lits, nums.get(end + 1..).unwrap_or(&[]))
+}
+
+// Unit propagation over all of db reaches a conflict from the negated lemma
+fn rup(db: &[Clause], lemma: &[Lit]) -> bool {
+    let mut assignment = match Assignment::negate(lemma) {
+        Some(assignment) => assignment,
+        None => return true,
+    };
+    loop {
+        let mut changed = false;
+        for clause in db {
+            let mut unassigned = None;
+            let mut open = 0;
+            let mut sat = false;
+            for &l in clause {
+                match assignment.lit_value(l) {
+                    Some(true) => {
+                        sat = true;
+                        break;
+                    }
+                    Some(false) => {}
+                    // Repeated literals count once
+                    None if unassigned != Some(l) => {
+                        open += 1;
+                        unassigned = Some(l);
+                    }
+                    None => {}
+                }
+            }
+            if sat {
+                continue;
+            }
+            match (open, unassigned) {
+                (0, _) => return true,
+                (1, Some(l)) => {
+                    assignment.set(l);
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+        if !changed {
+            return false;
+        }
+    }
+}
+
+fn sorted(clause: &[Lit]) -> Clause {
+    let mut key = clause.to_vec();
+    key.sort_unstable();
+    key
+}
+
+/**
+ * Spec: checks a DRAT proof (RUP steps and deletions, no RAT steps) against
+ * clauses: every added lemma must be RUP and the empty clause must be added.
+ */
+pub fn check_drat(clauses: &[Clause], proof: &str) -> Result<(), String> {
+    let mut db: Vec<Clause> = clauses.to_vec();
+    for line in proof.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+        let (deletion, nums) = parse_proof_line(line, 0)?;
+        let (lemma, _) = split_zero(&nums);
+        if deletion {
+            let key = sorted(&lemma);
+            if let Some(pos) = db.iter().position(|c| sorted(c) == key) {
+                db.swap_remove(pos);
+            }
+            continue;
+        }
+        if !rup(&db, &lemma) {
+            return Err(format!("lemma '{line}' is not RUP"));
+        }
+        if lemma.is_empty() {
+            return Ok(());
+        }
+        db.push(lemma);
+    }
+    Err(String::from("the proof does not derive the empty clause"))
+}
+
+/**
+ * Spec: checks an LRAT proof (RUP hints only) against clauses, whose ids are
+ * 1..=m: every added clause must propagate to a conflict through its hints,
+ * in order, and the empty clause must be added.
+ */
+pub fn check_lrat(clauses: &[Clause], proof: &str) -> Result<(), String> {
+    let mut db: HashMap<i64, Clause> = clauses
+        .iter()
+        .enumerate()
+        .map(|(i, clause)| (i as i64 + 1, clause.clone()))
+        .collect();
+    for line in proof.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+        let (deletion, nums) = parse_proof_line(line, 1)?;
+        let (&id, rest) = nums
+            .split_first()
+            .ok_or_else(|| format!("bad proof line '{line}'"))?;
+        if deletion {
+            for gone in rest.iter().take_while(|&&n| n != 0) {
+                db.remove(gone);
+            }
+            continue;
+        }
+        let (lits, rest) = split_zero(rest);
+        let hints = rest.iter().take_while(|&&n| n != 0);
+        let mut assignment = Assignment::negate(&lits).ok_or(format!("tautology in '{line}'"))?;
+        let mut conflict = false;
+        for &h in hints {
+            let hint = db
+                .get(&h)
+                .ok_or_else(|| format!("unknown or RAT hint {h} in '{line}'"))?;
+            let mut unassigned = Vec::new();
+            for &l in hint {
+                match assignment.lit_value(l) {
+                    Some(true) => return Err(format!("hint {h} is satisfied in '{line}'")),
+                    Some(false) => {}
+                    None => unassigned.push(l),
+                }
+            }
+            unassigned.sort_unstable();
+            unassigned.dedup();
+            match unassigned[..] {
+                [] => {
+                    conflict = true;
+                    break;
+                }
+                [l] => {
+                    assignment.set(l);
+                }
+                _ => return Err(format!("hint {h} is not unit in '{line}'")),
+            }
+        }
+        if !conflict {
+            return Err(format!("hints do not reach a conflict in '{line}'"));
+        }
+        if lits.is_empty() {
+            return Ok(());
+        }
+        db.insert(id, lits);
+    }
+    Err(String::from("the proof does not derive the empty clause"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cnf::model_from_lits;
+    use crate::preprocess::testing::{is_sat, random_cnfs, satisfies};
+
+    fn drat(steps: &[Step]) -> String {
+        let mut out = Vec::new();
+        write_drat(steps, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn lrat(steps: &[Step]) -> String {
+        let mut out = Vec::new();
+        write_lrat(steps, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // All four sign combinations of 1 and 2
+    fn square() -> Vec<Clause> {
+        vec![vec![1, 2], vec![1, -2], vec![-1, 2], vec![-1, -2]]
+    }
+
+    #[test]
+    fn refutes_exactly_the_unsat_cnfs() {
+        for db in random_cnfs(45, 300) {
+            match refute(&db.clauses, db.num_vars) {
+                Ok(lits) => {
+                    let model = model_from_lits(&lits, db.num_vars);
+                    assert!(satisfies(&model, &db.clauses), "{db:?}");
+                }
+                Err(steps) => {
+                    assert!(!is_sat(&db));
+                    assert_eq!(steps.last().unwrap().lits, Vec::<Lit>::new());
+                    assert_eq!(check_drat(&db.clauses, &drat(&steps)), Ok(()));
+                    assert_eq!(check_lrat(&db.clauses, &lrat(&steps)), Ok(()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn an_empty_clause_is_its_own_refutation() {
+        let steps = refute(&[vec![1], vec![]], 1).unwrap_err();
+        assert_eq!(lrat(&steps), "3 0 2 0\n");
+    }
+
+    #[test]
+    fn accepts_hand_written_proofs() {
+        let clauses = square();
+        assert_eq!(check_drat(&clauses, "c lemma\n1 0\nd 1 2 0\n0\n"), Ok(()));
+        assert_eq!(
+            check_lrat(&clauses, "5 1 0 1 2 0\n5 d 1 2 0\n6 0 5 3 4 0\n"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_proofs() {
+        let clauses = square();
+        // Not RUP without the clauses it would need
+        assert!(check_drat(&clauses[..2], "2 0\n0\n").is_err());
+        assert!(check_drat(&clauses, "1 0\n").is_err());
+        // Deleting (1 2) and (1 -2) first leaves 1 unprovable
+        assert!(check_drat(&clauses, "d 1 2 0\nd 2 1 0\nd 1 -2 0\n1 0\n0\n").is_err());
+        assert!(check_lrat(&clauses, "5 1 0 1 0\n").is_err());
+        assert!(check_lrat(&clauses, "5 1 0 1 2 0\n6 0 3 4 0\n").is_err());
+        assert!(check_lrat(&clauses, "5 1 0 7 0\n").is_err());
+        // A deleted clause can no longer be a hint
+        assert!(check_lrat(&clauses, "5 d 2 0\n5 1 0 1 2 0\n6 0 5 3 4 0\n").is_err());
+        assert!(check_lrat(&clauses, "5 1 0 1 2 0\n").is_err());
+        assert!(check_lrat(&clauses, "5 x 0\n").is_err());
+    }
+
+    #[test]
+    fn searches_deeper_than_the_stack() {
+        // One level per clause, each with a fresh pair of variables
+        let n = 200_000;
+        let mut clauses: Vec<Clause> = (1..=n).map(|i| vec![2 * i - 1, 2 * i]).collect();
+        let lits = refute(&clauses, 2 * n as usize).unwrap();
+        assert_eq!(lits.len(), n as usize);
+        // The same chain ending in a conflict on its last variable
+        clauses.push(vec![-(2 * n - 1)]);
+        clauses.push(vec![-(2 * n)]);
+        let steps = refute(&clauses, 2 * n as usize).unwrap_err();
+        assert_eq!(steps.last().unwrap().lits, Vec::<Lit>::new());
+    }
+}
@@ -0,0 +1,51 @@
+// Clause provenance of the cubes: every cube of the cross product takes one
+// disjunct from the DNF of each part (a clause, an AMO/EO group, a cardinality
+// constraint or the XOR system), and with tracking on the engine records
+// which one, so a cube can be mapped back to what satisfied each part.
+use crate::cnf::Lit;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TracedCube {
+    pub lits: Vec<Lit>,
+    // chosen[i] = the index of the disjunct taken from the DNF of part i
+    pub chosen: Vec<usize>,
+}
+
+/**
+ * Spec: the parts that take the same disjunct in every cube, with that
+ * disjunct's index: its literals are true in every model. Empty if there are
+ * no cubes.
+ */
+pub fn forced(cubes: &[TracedCube]) -> Vec<(usize, usize)> {
+    let first = match cubes.first() {
+        Some(first) => first,
+        None => return Vec::new(),
+    };
+    first
+        .chosen
+        .iter()
+        .enumerate()
+        .filter(|&(i, &j)| cubes.iter().all(|cube| cube.chosen[i] == j))
+        .map(|(i, &j)| (i, j))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traced(lits: &[Lit], chosen: &[usize]) -> TracedCube {
+        TracedCube {
+            lits: lits.to_vec(),
+            chosen: chosen.to_vec(),
+        }
+    }
+
+    #[test]
+    fn forced_parts_pick_the_same_disjunct_everywhere() {
+        let cubes = [traced(&[1, -2, 3], &[0, 1, 2]), traced(&[1, 2], &[0, 0, 2])];
+        assert_eq!(forced(&cubes), vec![(0, 0), (2, 2)]);
+        assert_eq!(forced(&cubes[..1]), vec![(0, 0), (1, 1), (2, 2)]);
+        assert!(forced(&[]).is_empty());
+    }
+}
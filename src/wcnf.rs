@@ -0,0 +1,255 @@
+// Weighted MaxSAT over WCNF input, in either format of the MaxSAT evaluations:
+//   old: "p wcnf <vars> <clauses> <top>", then "<weight> <lits> 0" lines,
+//        a clause is hard iff its weight is at least top
+//   new: no header, "h <lits> 0" hard and "<weight> <lits> 0" soft lines
+// The DNF of the hard clauses gives the candidate cubes; inside every cube the
+// cheapest completion w.r.t. the soft clauses is found by branch and bound.
+use std::io::BufRead;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::cnf::{model_from_lits, parse_clause, var, Clause, ClauseDb, Lit, Model};
+use crate::dnf::Cube;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoftClause {
+    pub weight: u64,
+    pub lits: Clause,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WcnfProblem {
+    pub hard: ClauseDb,
+    pub soft: Vec<SoftClause>,
+}
+
+/**
+ * Spec: parses a WCNF file (old or new format). hard.num_vars covers the
+ * variables of the soft clauses too.
+ */
+pub fn parse_wcnf<R: BufRead>(reader: R) -> WcnfProblem {
+    let mut problem = WcnfProblem::default();
+    let mut top: Option<u64> = None;
+    for line in reader.lines() {
+        let line = line.expect("String needed");
+        let good_line = line.trim();
+        if good_line.is_empty() || good_line.starts_with('c') {
+            continue;
+        }
+        if good_line.starts_with('p') {
+            let header: Vec<&str> = good_line.split_whitespace().collect();
+            let bad_header = || -> ! { panic!("CRITICAL ERROR PARSING HEADER: '{good_line}'") };
+            problem.hard.num_vars = header
+                .get(2)
+                .and_then(|n| n.parse().ok())
+                .unwrap_or_else(|| bad_header());
+            top = header
+                .get(4)
+                .map(|t| t.parse().unwrap_or_else(|_| bad_header()));
+            continue;
+        }
+        let (weight, rest) = good_line
+            .split_once(char::is_whitespace)
+            .unwrap_or((good_line, ""));
+        let lits = parse_clause(rest);
+        for &l in &lits {
+            problem.hard.num_vars = problem.hard.num_vars.max(var(l));
+        }
+        if weight == "h" {
+            problem.hard.clauses.push(lits);
+            continue;
+        }
+        let weight: u64 = weight
+            .parse()
+            .unwrap_or_else(|_| panic!("CRITICAL ERROR PARSING WEIGHT: '{good_line}'"));
+        if top.is_some_and(|top| weight >= top) {
+            problem.hard.clauses.push(lits);
+        } else {
+            problem.soft.push(SoftClause { weight, lits });
+        }
+    }
+    problem
+}
+
+// Branch and bound over the variables a cube leaves free
+struct Completion<'a> {
+    soft: &'a [SoftClause],
+    value: Vec<Option<bool>>,
+    // The best cost found so far, across all cubes
+    bound: &'a AtomicU64,
+    best: Option<(u64, Vec<Option<bool>>)>,
+}
+
+impl Completion<'_> {
+    // The weight of the falsified soft clauses and an open one's literal, if any
+    fn evaluate(&self) -> (u64, Option<Lit>) {
+        let mut cost = 0;
+        let mut open = None;
+        for clause in self.soft {
+            let mut free = None;
+            let mut sat = false;
+            for &l in &clause.lits {
+                match self.value[var(l)] {
+                    Some(val) if val == (l > 0) => {
+                        sat = true;
+                        break;
+                    }
+                    Some(_) => {}
+                    None => free = Some(l),
+                }
+            }
+            if sat {
+                continue;
+            }
+            match free {
+                Some(l) => open = open.or(Some(l)),
+                None => cost += clause.weight,
+            }
+        }
+        (cost, open)
+    }
+
+    fn branch(&mut self) {
+        let (cost, open) = self.evaluate();
+        if cost >= self.bound.load(Ordering::Relaxed) {
+            return;
+        }
+        let l = match open {
+            Some(l) => l,
+            None => {
+                self.bound.fetch_min(cost, Ordering::Relaxed);
+                self.best = Some((cost, self.value.clone()));
+                return;
+            }
+        };
+        // Satisfying the open clause first finds good bounds early
+        for val in [l > 0, l < 0] {
+            self.value[var(l)] = Some(val);
+            self.branch();
+        }
+        self.value[var(l)] = None;
+    }
+}
+
+/**
+ * Spec: the minimum weight of falsified soft clauses over the models of the
+ * cubes, with a model reaching it (variables left open default to false).
+ * None if there are no cubes (the hard clauses are UNSAT).
+ */
+pub fn solve(cubes: &[Cube], soft: &[SoftClause], num_vars: usize) -> Option<(u64, Model)> {
+    let bound = AtomicU64::new(u64::MAX);
+    cubes
+        .par_iter()
+        .filter_map(|cube| {
+            let mut value = vec![None; num_vars + 1];
+            for &l in cube {
+                value[var(l)] = Some(l > 0);
+            }
+            let mut completion = Completion {
+                soft,
+                value,
+                bound: &bound,
+                best: None,
+            };
+            completion.branch();
+            completion.best
+        })
+        .min_by_key(|(cost, _)| *cost)
+        .map(|(cost, value)| {
+            let lits: Vec<Lit> = value
+                .iter()
+                .enumerate()
+                .filter_map(|(v, val)| val.map(|val| if val { v as Lit } else { -(v as Lit) }))
+                .collect();
+            (cost, model_from_lits(&lits, num_vars))
+        })
+}
+
+// MaxSAT evaluation style "v 0110" model line, one digit per variable
+pub fn model_line(model: &Model) -> String {
+    let digits: String = model
+        .iter()
+        .skip(1)
+        .map(|&val| if val { '1' } else { '0' })
+        .collect();
+    format!("v {digits}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cnf::lit_true;
+
+    fn soft(weight: u64, lits: &[Lit]) -> SoftClause {
+        SoftClause {
+            weight,
+            lits: lits.to_vec(),
+        }
+    }
+
+    // The weight of the soft clauses the model falsifies
+    fn cost(soft: &[SoftClause], model: &Model) -> u64 {
+        soft.iter()
+            .filter(|clause| !clause.lits.iter().any(|&l| lit_true(model, l)))
+            .map(|clause| clause.weight)
+            .sum()
+    }
+
+    #[test]
+    fn parses_both_formats() {
+        let old = parse_wcnf("c old\np wcnf 3 3 10\n10 1 2 0\n3 -1 0\n12 -3 0\n".as_bytes());
+        let new = parse_wcnf("h 1 2 0\n3 -1 0\nh -3 0\n".as_bytes());
+        for problem in [old, new] {
+            assert_eq!(problem.hard.num_vars, 3);
+            assert_eq!(problem.hard.clauses, vec![vec![1, 2], vec![-3]]);
+            assert_eq!(problem.soft, vec![soft(3, &[-1])]);
+        }
+    }
+
+    #[test]
+    fn finds_the_cheapest_model() {
+        // Hard: 1 or 2. Soft: -1 (5), -2 (3), 1 (1), 3 (2)
+        let soft = [soft(5, &[-1]), soft(3, &[-2]), soft(1, &[1]), soft(2, &[3])];
+        let (best, model) = solve(&[vec![1], vec![-1, 2]], &soft, 3).unwrap();
+        assert_eq!(best, 4);
+        assert_eq!(model, vec![false, false, true, true]);
+        assert_eq!(cost(&soft, &model), best);
+        assert_eq!(model_line(&model), "v 011");
+    }
+
+    #[test]
+    fn no_cubes_is_unsat() {
+        assert!(solve(&[], &[soft(1, &[1])], 1).is_none());
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let soft = [
+            soft(4, &[1, 2]),
+            soft(3, &[-1, 3]),
+            soft(2, &[-2, -3]),
+            soft(2, &[-3, 4]),
+            soft(1, &[-4]),
+            soft(5, &[2, -4]),
+        ];
+        let cube_sets = [vec![vec![]], vec![vec![1, -2], vec![-1, 4]], vec![vec![3]]];
+        for cubes in &cube_sets {
+            let (best, model) = solve(cubes, &soft, 4).unwrap();
+            assert_eq!(cost(&soft, &model), best);
+            assert!(cubes
+                .iter()
+                .any(|cube| cube.iter().all(|&l| lit_true(&model, l))));
+            let brute = (0..16u32)
+                .map(|bits| (0..=4).map(|v| v > 0 && bits >> (v - 1) & 1 == 1).collect())
+                .filter(|model: &Model| {
+                    cubes
+                        .iter()
+                        .any(|cube| cube.iter().all(|&l| lit_true(model, l)))
+                })
+                .map(|model| cost(&soft, &model))
+                .min();
+            assert_eq!(Some(best), brute);
+        }
+    }
+}
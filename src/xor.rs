@@ -0,0 +1,244 @@
+// Gaussian elimination over GF(2) for the XOR constraints.
+//
+// Each XOR is a row: the set of its variables and the parity they sum to (a
+// negated literal flips the parity). After Gauss-Jordan elimination every row
+// has a pivot variable occurring in no other row, so each assignment to the
+// remaining free variables fixes all the pivots: the system's DNF is one cube
+// per free assignment, instead of the 2^(n-1) cubes of every n-literal XOR.
+use std::fmt;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::cnf::{var, Lit};
+use crate::dnf::Cube;
+
+#[derive(Debug, Clone)]
+struct Row {
+    // Bit v is set iff variable v occurs (an odd number of times)
+    bits: Vec<u64>,
+    rhs: bool,
+}
+
+impl Row {
+    fn has(&self, v: usize) -> bool {
+        self.bits[v / 64] >> (v % 64) & 1 == 1
+    }
+
+    fn add(&mut self, other: &Row) {
+        for (a, b) in self.bits.iter_mut().zip(&other.bits) {
+            *a ^= b;
+        }
+        self.rhs ^= other.rhs;
+    }
+}
+
+// The reduced system: pivot rows and the free variables they mention
+#[derive(Debug, Clone)]
+pub struct XorSystem {
+    rows: Vec<(usize, Row)>,
+    free: Vec<usize>,
+    words: usize,
+    constraints: usize,
+}
+
+// The size of reduced systems, summed over the ones added
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XorStats {
+    pub constraints: usize,
+    pub rank: usize,
+    pub free: usize,
+}
+
+impl XorStats {
+    pub fn add(&mut self, system: &XorSystem) {
+        self.constraints += system.constraints;
+        self.rank += system.rows.len();
+        self.free += system.free.len();
+    }
+}
+
+impl fmt::Display for XorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "XOR: {} constraints, rank {}, {} free variables",
+            self.constraints, self.rank, self.free
+        )
+    }
+}
+
+/**
+ * Spec: Gauss-Jordan elimination of xors over variables 1..=num_vars.
+ * Returns None if the system is inconsistent (some row reduces to 0 = 1).
+ */
+pub fn eliminate(xors: &[Vec<Lit>], num_vars: usize) -> Option<XorSystem> {
+    let words = num_vars / 64 + 1;
+    let mut rows: Vec<Row> = xors
+        .iter()
+        .map(|xor| {
+            let mut row = Row {
+                bits: vec![0; words],
+                rhs: true,
+            };
+            for &l in xor {
+                row.bits[var(l) / 64] ^= 1 << (var(l) % 64);
+                row.rhs ^= l < 0;
+            }
+            row
+        })
+        .collect();
+
+    let mut pivots = Vec::new();
+    for v in 1..=num_vars {
+        let rank = pivots.len();
+        let found = match (rank..rows.len()).find(|&r| rows[r].has(v)) {
+            Some(r) => r,
+            None => continue,
+        };
+        rows.swap(rank, found);
+        let pivot_row = rows[rank].clone();
+        for (r, row) in rows.iter_mut().enumerate() {
+            if r != rank && row.has(v) {
+                row.add(&pivot_row);
+            }
+        }
+        pivots.push(v);
+    }
+    // The rows past the rank are empty, they must have parity 0
+    if rows[pivots.len()..].iter().any(|row| row.rhs) {
+        return None;
+    }
+    rows.truncate(pivots.len());
+
+    let free: Vec<usize> = (1..=num_vars)
+        .filter(|&v| !pivots.contains(&v) && rows.iter().any(|row| row.has(v)))
+        .collect();
+    Some(XorSystem {
+        rows: pivots.into_iter().zip(rows).collect(),
+        free,
+        words,
+        constraints: xors.len(),
+    })
+}
+
+impl XorSystem {
+    /**
+     * Spec: the models of the system as disjoint cubes over its variables,
+     * one per assignment of the free variables. None if there are too many
+     * free variables to enumerate (64 or more).
+     */
+    pub fn cubes(&self) -> Option<Vec<Cube>> {
+        if self.free.len() >= 64 {
+            return None;
+        }
+        let cubes = (0..1u64 << self.free.len())
+            .into_par_iter()
+            .map(|mask| {
+                let mut cube: Cube = Vec::with_capacity(self.free.len() + self.rows.len());
+                let mut true_free = vec![0u64; self.words];
+                for (i, &v) in self.free.iter().enumerate() {
+                    let val = mask >> i & 1 == 1;
+                    if val {
+                        true_free[v / 64] |= 1 << (v % 64);
+                    }
+                    cube.push(if val { v as Lit } else { -(v as Lit) });
+                }
+                for (pivot, row) in &self.rows {
+                    // pivot = rhs xor (the true free variables of the row)
+                    let ones: u32 = row
+                        .bits
+                        .iter()
+                        .zip(&true_free)
+                        .map(|(a, b)| (a & b).count_ones())
+                        .sum();
+                    let val = row.rhs ^ (ones % 2 == 1);
+                    cube.push(if val { *pivot as Lit } else { -(*pivot as Lit) });
+                }
+                cube
+            })
+            .collect();
+        Some(cubes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every assignment to 1..=num_vars satisfying all the xors
+    fn solutions(xors: &[Vec<Lit>], num_vars: usize) -> Vec<Vec<Lit>> {
+        (0..1u32 << num_vars)
+            .map(|bits| {
+                (1..=num_vars as Lit)
+                    .map(|v| if bits >> (v - 1) & 1 == 1 { v } else { -v })
+                    .collect::<Vec<Lit>>()
+            })
+            .filter(|model| {
+                xors.iter()
+                    .all(|xor| xor.iter().filter(|l| model.contains(l)).count() % 2 == 1)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inconsistent_systems_have_no_solution() {
+        // 1 ^ 2 and 2 ^ 3 force 1 ^ 3 even, not odd
+        assert!(eliminate(&[vec![1, 2], vec![2, 3], vec![1, 3]], 3).is_none());
+        assert!(eliminate(&[vec![1, 1]], 1).is_none());
+        // x ^ -x always holds
+        assert!(eliminate(&[vec![1, -1]], 1).is_some());
+    }
+
+    #[test]
+    fn reduces_to_the_rank() {
+        let system = eliminate(&[vec![1, 2, 3], vec![2, 3, 4], vec![1, -4]], 4).unwrap();
+        let mut stats = XorStats::default();
+        stats.add(&system);
+        assert_eq!(
+            stats,
+            XorStats {
+                constraints: 3,
+                rank: 2,
+                free: 2
+            }
+        );
+        assert_eq!(system.cubes().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn cubes_are_the_solutions() {
+        let systems = [
+            vec![vec![1, 2, 3], vec![-2, 4]],
+            vec![vec![1, 2], vec![2, 3], vec![3, 4], vec![-5]],
+            vec![vec![1, -2, 3, 4, 5], vec![1, 3], vec![2, 2, 4]],
+        ];
+        for xors in systems {
+            let mut cubes = eliminate(&xors, 5).unwrap().cubes().unwrap();
+            // Complete every cube with the unmentioned variables, which are free
+            for v in 1..=5 {
+                if !xors.iter().flatten().any(|&l| var(l) == v as usize) {
+                    cubes = cubes
+                        .into_iter()
+                        .flat_map(|cube| {
+                            [v, -v].map(|l| cube.iter().copied().chain([l]).collect::<Cube>())
+                        })
+                        .collect();
+                }
+            }
+            for cube in &mut cubes {
+                cube.sort_unstable_by_key(|&l| var(l));
+            }
+            cubes.sort_unstable();
+            let mut expected = solutions(&xors, 5);
+            expected.sort_unstable();
+            assert_eq!(cubes, expected, "{xors:?}");
+        }
+    }
+
+    #[test]
+    fn too_many_free_variables_are_not_enumerated() {
+        let xor: Vec<Lit> = (1..=70).collect();
+        let system = eliminate(&[xor], 70).unwrap();
+        assert!(system.cubes().is_none());
+    }
+}